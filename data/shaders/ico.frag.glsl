@@ -17,11 +17,11 @@ layout(set=0, binding=0) uniform Uniforms
     uint selected;
 };
 
-layout(set=1, binding=0) uniform texture2DArray t_diffuse;
-layout(set=1, binding=1) uniform sampler s_diffuse;
+layout(set=0, binding=1) uniform texture2DArray t_diffuse;
+layout(set=0, binding=2) uniform sampler s_diffuse;
 
-layout(set=2, binding=0) uniform texture2D t_normal;
-layout(set=2, binding=1) uniform sampler s_normal;
+layout(set=0, binding=3) uniform texture2D t_normal;
+layout(set=0, binding=4) uniform sampler s_normal;
 
 layout(location=0) out vec4 f_color;
 
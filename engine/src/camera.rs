@@ -1,5 +1,11 @@
 use crate::Size;
 
+pub mod controller;
+pub mod transition;
+
+/// Extra room around the unit sphere at zoom 1.
+pub const MARGIN: f32 = 1.15;
+
 pub struct Camera {
     pub rot: glam::Vec3,
     pub target: glam::Vec3,
@@ -22,34 +28,77 @@ impl Camera {
         }
     }
 
-    pub fn build(&self, perspective: bool) -> glam::Mat4 {
-        let margin = 1.15;
+    /// Where the camera sits, the orthographic camera keeps a unit distance
+    /// since only its direction matters.
+    pub fn eye(&self, perspective: bool) -> glam::Vec3 {
         if perspective {
-            let dist = (margin) / f32::tan(self.fovy / 2.0);
-            let view = glam::Mat4::look_at_rh(
-                self.target + (dist * self.zoom * self.rot),
-                self.target,
-                self.up,
-            );
-            let proj = glam::Mat4::perspective_rh(self.fovy, self.aspect, 0.01, 1000.0);
-            proj * view
+            let dist = MARGIN / f32::tan(self.fovy / 2.0);
+            self.target + (dist * self.zoom * self.rot)
+        } else {
+            self.target + self.rot
+        }
+    }
+
+    pub fn view(&self, perspective: bool) -> glam::Mat4 {
+        glam::Mat4::look_at_rh(self.eye(perspective), self.target, self.up)
+    }
+
+    pub fn projection(&self, perspective: bool) -> glam::Mat4 {
+        if perspective {
+            glam::Mat4::perspective_rh(self.fovy, self.aspect, 0.01, 1000.0)
         } else {
-            let view = glam::Mat4::look_at_rh(self.target + self.rot, self.target, self.up);
-            let zoom = margin * self.zoom;
-            let proj = glam::Mat4::orthographic_rh(
+            let zoom = MARGIN * self.zoom;
+            glam::Mat4::orthographic_rh(
                 -zoom * self.aspect,
                 zoom * self.aspect,
                 -zoom,
                 zoom,
                 -1000.0,
                 1000.0,
-            );
-            proj * view
+            )
+        }
+    }
+
+    pub fn build(&self, perspective: bool) -> glam::Mat4 {
+        self.projection(perspective) * self.view(perspective)
+    }
+
+    /// Ray through a pixel, `px` is measured from the top left of a target of `size`.
+    pub fn screen_point_to_ray(&self, px: glam::Vec2, size: Size, perspective: bool) -> Ray {
+        let ndc = glam::vec2(
+            2.0 * px.x / size.width as f32 - 1.0,
+            1.0 - 2.0 * px.y / size.height as f32,
+        );
+        let inverse = self.build(perspective).inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
         }
     }
 
-    pub fn view(&self) -> glam::Mat4 {
-        glam::Mat4::look_at_rh(self.rot, self.target, self.up)
+    /// Pixel position and depth of a world point, `None` behind a perspective camera.
+    pub fn world_to_screen(
+        &self,
+        point: glam::Vec3,
+        size: Size,
+        perspective: bool,
+    ) -> Option<glam::Vec3> {
+        let clip = self.build(perspective) * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(glam::vec3(
+            (ndc.x + 1.0) * 0.5 * size.width as f32,
+            (1.0 - ndc.y) * 0.5 * size.height as f32,
+            ndc.z,
+        ))
+    }
+
+    pub fn frustum(&self, perspective: bool) -> Frustum {
+        Frustum::from_matrix(self.build(perspective))
     }
 
     pub fn resize(&mut self, size: Size) {
@@ -69,3 +118,217 @@ impl Camera {
         self.up = rot.mul_vec3(self.up);
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: glam::Vec3,
+    /// Unit length.
+    pub direction: glam::Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> glam::Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// Planes facing inwards, `xyz` is the unit normal and `w` the offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a view projection matrix with wgpu's 0 to 1 depth.
+    pub fn from_matrix(m: glam::Mat4) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2];
+        for plane in planes.iter_mut() {
+            *plane = *plane / plane.truncate().length();
+        }
+        Self { planes }
+    }
+
+    fn distance(plane: glam::Vec4, point: glam::Vec3) -> f32 {
+        plane.truncate().dot(point) + plane.w
+    }
+
+    pub fn contains_point(&self, point: glam::Vec3) -> bool {
+        self.planes.iter().all(|&p| Self::distance(p, point) >= 0.0)
+    }
+
+    /// Conservative, spheres near the corners can pass without touching.
+    pub fn intersects_sphere(&self, center: glam::Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|&p| Self::distance(p, center) >= -radius)
+    }
+
+    /// Conservative like [`Frustum::intersects_sphere`].
+    pub fn intersects_aabb(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+        self.planes.iter().all(|&p| {
+            // the corner furthest along the plane normal
+            let corner = glam::vec3(
+                if p.x >= 0.0 { max.x } else { min.x },
+                if p.y >= 0.0 { max.y } else { min.y },
+                if p.z >= 0.0 { max.z } else { min.z },
+            );
+            Self::distance(p, corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, vec3, vec4, Mat4, Vec3};
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    /// Looking down -z at the origin with a 90 degree field of view, so
+    /// `tan(fovy / 2)` is 1.
+    fn camera() -> Camera {
+        Camera {
+            rot: Vec3::Z,
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: 2.0,
+            fovy: std::f32::consts::FRAC_PI_2,
+            zoom: 1.0,
+        }
+    }
+
+    fn assert_mat_eq(actual: Mat4, expected: Mat4) {
+        assert!(
+            actual.abs_diff_eq(expected, EPSILON),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn perspective_view() {
+        // the eye sits `MARGIN / tan(fovy / 2)` up the z axis
+        let expected = Mat4::from_cols(
+            vec4(1.0, 0.0, 0.0, 0.0),
+            vec4(0.0, 1.0, 0.0, 0.0),
+            vec4(0.0, 0.0, 1.0, 0.0),
+            vec4(0.0, 0.0, -MARGIN, 1.0),
+        );
+        assert_mat_eq(camera().view(true), expected);
+    }
+
+    #[test]
+    fn orthographic_view() {
+        let expected = Mat4::from_cols(
+            vec4(1.0, 0.0, 0.0, 0.0),
+            vec4(0.0, 1.0, 0.0, 0.0),
+            vec4(0.0, 0.0, 1.0, 0.0),
+            vec4(0.0, 0.0, -1.0, 1.0),
+        );
+        assert_mat_eq(camera().view(false), expected);
+    }
+
+    #[test]
+    fn perspective_projection() {
+        let (near, far) = (0.01, 1000.0);
+        let depth = far / (near - far);
+        let expected = Mat4::from_cols(
+            vec4(0.5, 0.0, 0.0, 0.0),
+            vec4(0.0, 1.0, 0.0, 0.0),
+            vec4(0.0, 0.0, depth, -1.0),
+            vec4(0.0, 0.0, depth * near, 0.0),
+        );
+        assert_mat_eq(camera().projection(true), expected);
+    }
+
+    #[test]
+    fn orthographic_projection() {
+        // half extents of `MARGIN * zoom`, scaled by the aspect horizontally
+        let expected = Mat4::from_cols(
+            vec4(1.0 / (2.0 * MARGIN), 0.0, 0.0, 0.0),
+            vec4(0.0, 1.0 / MARGIN, 0.0, 0.0),
+            vec4(0.0, 0.0, -1.0 / 2000.0, 0.0),
+            vec4(0.0, 0.0, 0.5, 1.0),
+        );
+        assert_mat_eq(camera().projection(false), expected);
+    }
+
+    #[test]
+    fn screen_point_round_trip() {
+        let size = Size::new(800, 400);
+        let mut camera = camera();
+        camera.rot = vec3(1.0, 2.0, 3.0).normalize();
+        camera.zoom = 0.7;
+        let pixels = [
+            vec2(400.0, 200.0),
+            vec2(0.0, 0.0),
+            vec2(799.0, 13.0),
+            vec2(120.5, 377.25),
+        ];
+        for &perspective in [true, false].iter() {
+            for &px in pixels.iter() {
+                let ray = camera.screen_point_to_ray(px, size, perspective);
+                assert!((ray.direction.length() - 1.0).abs() < EPSILON);
+                for &distance in [0.5, 1.0, 2.0].iter() {
+                    let screen = camera
+                        .world_to_screen(ray.at(distance), size, perspective)
+                        .unwrap();
+                    assert!(
+                        (screen.x - px.x).abs() < 0.05 && (screen.y - px.y).abs() < 0.05,
+                        "{:?} came back as {:?}, perspective: {}",
+                        px,
+                        screen,
+                        perspective
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn behind_perspective_camera() {
+        let size = Size::new(800, 400);
+        let behind = vec3(0.0, 0.0, 5.0);
+        assert_eq!(camera().world_to_screen(behind, size, true), None);
+        assert!(camera().world_to_screen(behind, size, false).is_some());
+    }
+
+    #[test]
+    fn frustum_spheres() {
+        for &perspective in [true, false].iter() {
+            let frustum = camera().frustum(perspective);
+            // the visible half width at the target is `MARGIN * aspect`, the
+            // perspective planes slant outwards behind it
+            let edge = MARGIN * 2.0;
+            assert!(frustum.contains_point(Vec3::ZERO));
+            assert!(frustum.intersects_sphere(Vec3::ZERO, 0.5));
+            assert!(!frustum.intersects_sphere(vec3(edge + 1.5, 0.0, 0.0), 0.5));
+            assert!(!frustum.contains_point(vec3(edge + 0.2, 0.0, 0.0)));
+            assert!(frustum.intersects_sphere(vec3(edge + 0.2, 0.0, 0.0), 0.5));
+            assert!(!frustum.intersects_sphere(vec3(0.0, -MARGIN - 1.0, 0.0), 0.5));
+        }
+        // only the perspective camera has its near plane close by
+        let frustum = camera().frustum(true);
+        assert!(!frustum.intersects_sphere(vec3(0.0, 0.0, 5.0), 0.5));
+        assert!(frustum.intersects_sphere(vec3(0.0, 0.0, 1.5), 0.5));
+    }
+
+    #[test]
+    fn frustum_aabbs() {
+        for &perspective in [true, false].iter() {
+            let frustum = camera().frustum(perspective);
+            let edge = MARGIN * 2.0;
+            assert!(frustum.intersects_aabb(Vec3::splat(-0.5), Vec3::splat(0.5)));
+            assert!(!frustum.intersects_aabb(vec3(10.0, 10.0, -1.0), vec3(11.0, 11.0, 1.0)));
+            assert!(
+                frustum.intersects_aabb(vec3(edge - 0.3, -0.1, -0.1), vec3(edge + 0.7, 0.1, 0.1))
+            );
+            assert!(
+                !frustum.intersects_aabb(vec3(edge + 0.5, -0.1, -0.1), vec3(edge + 1.5, 0.1, 0.1))
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::{f32::consts::FRAC_PI_2, ops::RangeInclusive};
+
+use glam::{Quat, Vec2, Vec3};
+
+use crate::inputs::{Actions, AxisBinding, Bindings, Chord, InputState};
+
+use super::{Camera, MARGIN};
+
+/// Held while dragging the view.
+pub const DRAG: &str = "drag";
+pub const LOOK_X: &str = "look_x";
+pub const LOOK_Y: &str = "look_y";
+pub const ROLL: &str = "roll";
+pub const ZOOM: &str = "zoom";
+
+/// Closest the orbit camera gets to the poles with `north_up`.
+const MAX_ELEVATION: f32 = FRAC_PI_2 - 0.01;
+/// Angular speed below which the camera stops coasting, radians per second.
+const MIN_VELOCITY: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// Yaw around the up axis and pitch around the screen's horizontal axis.
+    Orbit,
+    /// Rotate around the axis perpendicular to the drag, free of poles.
+    Trackball,
+}
+
+/// One tick of camera input, in normalized device coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ControllerInput {
+    /// Cursor movement while dragging, `None` when the view isn't held.
+    pub drag: Option<Vec2>,
+    /// Radians per second around the view direction.
+    pub roll: f32,
+    /// Zoom steps, positive zooms in.
+    pub zoom: f32,
+    pub cursor: Option<Vec2>,
+}
+
+impl ControllerInput {
+    /// Reads the controller's actions, see [`CameraController::bindings`].
+    pub fn from_actions(actions: &Actions, input: &InputState) -> Self {
+        let drag = if actions.pressed(DRAG) {
+            Some(glam::vec2(actions.axis(LOOK_X), actions.axis(LOOK_Y)))
+        } else {
+            None
+        };
+        Self {
+            drag,
+            roll: actions.axis(ROLL),
+            zoom: actions.axis(ZOOM),
+            cursor: input.cursor_ndc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    pub mode: ControllerMode,
+    /// Radians per unit of drag in normalized device coordinates.
+    pub sensitivity: f32,
+    /// Fraction of the angular velocity left after coasting for a second.
+    pub damping: f32,
+    /// Zoom factor per step is `exp(zoom_speed)`.
+    pub zoom_speed: f32,
+    /// How fast the zoom catches up with its target, per second.
+    pub zoom_smoothing: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Keep the pivot under the cursor while zooming.
+    pub zoom_to_cursor: bool,
+    /// Farthest the pivot moves from the origin when zooming to the cursor,
+    /// the radius of the planet.
+    pub pivot_radius: f32,
+    /// Orbit around world up and stop short of the poles.
+    pub north_up: bool,
+
+    velocity: Vec2,
+    zoom_target: Option<f32>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: ControllerMode::Orbit,
+            sensitivity: FRAC_PI_2,
+            damping: 0.02,
+            zoom_speed: 0.1,
+            zoom_smoothing: 12.0,
+            min_zoom: 0.1,
+            max_zoom: 2.0,
+            zoom_to_cursor: true,
+            pivot_radius: 1.0,
+            north_up: false,
+            velocity: Vec2::ZERO,
+            zoom_target: None,
+        }
+    }
+}
+
+impl CameraController {
+    pub fn new(mode: ControllerMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Default bindings: drag with the left mouse button and zoom with the wheel
+    /// in `mouse`, roll with Q and E in `keys`.
+    pub fn bindings(mouse: &str, keys: &str) -> Bindings {
+        let mut bindings = Bindings::default();
+        bindings
+            .action(mouse, DRAG, Chord::mouse(winit::event::MouseButton::Left))
+            .axis(mouse, LOOK_X, AxisBinding::CursorX { scale: 1.0 })
+            .axis(mouse, LOOK_Y, AxisBinding::CursorY { scale: 1.0 })
+            .axis(mouse, ZOOM, AxisBinding::Wheel { scale: 1.0 })
+            .axis(
+                keys,
+                ROLL,
+                AxisBinding::Keys {
+                    negative: Chord::key(winit::event::VirtualKeyCode::Q),
+                    positive: Chord::key(winit::event::VirtualKeyCode::E),
+                    scale: 1.275,
+                },
+            );
+        bindings
+    }
+
+    /// Angular velocity in radians per second, x along the screen and y across it.
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    /// Zoom levels the controller keeps the camera within.
+    pub fn zoom_range(&self) -> RangeInclusive<f32> {
+        self.min_zoom..=self.max_zoom
+    }
+
+    /// Eases the zoom towards `zoom` instead of following the wheel.
+    pub fn zoom_to(&mut self, zoom: f32) {
+        self.zoom_target = Some(zoom.clamp(self.min_zoom, self.max_zoom));
+    }
+
+    /// Stops coasting and any zoom in progress.
+    pub fn stop(&mut self) {
+        self.velocity = Vec2::ZERO;
+        self.zoom_target = None;
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &ControllerInput, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        match input.drag {
+            Some(drag) => self.velocity = drag * self.sensitivity / dt,
+            None => {
+                self.velocity *= self.damping.powf(dt);
+                if self.velocity.length() < MIN_VELOCITY {
+                    self.velocity = Vec2::ZERO;
+                }
+            }
+        }
+        let angle = self.velocity * dt;
+        if angle != Vec2::ZERO {
+            match self.mode {
+                ControllerMode::Orbit => self.orbit(camera, angle),
+                ControllerMode::Trackball => trackball(camera, angle),
+            }
+        }
+        if input.roll != 0.0 && !self.north_up {
+            camera.rotate(input.roll * dt);
+        }
+
+        self.zoom(camera, input, dt);
+    }
+
+    fn orbit(&mut self, camera: &mut Camera, angle: Vec2) {
+        let right = screen_right(camera);
+        let axis = if self.north_up { Vec3::Y } else { camera.up };
+        let yaw = Quat::from_axis_angle(axis, -angle.x);
+        let pitch = Quat::from_axis_angle(right, -angle.y);
+
+        if self.north_up {
+            let rot = yaw.mul_vec3(camera.rot);
+            let pitched = pitch.mul_vec3(rot);
+            if pitched.y.abs() <= MAX_ELEVATION.sin() {
+                camera.rot = pitched.normalize();
+            } else {
+                camera.rot = rot.normalize();
+                self.velocity.y = 0.0;
+            }
+            camera.up = Vec3::Y;
+        } else {
+            let rot = yaw * pitch;
+            camera.rot = rot.mul_vec3(camera.rot).normalize();
+            camera.up = rot.mul_vec3(camera.up).normalize();
+        }
+    }
+
+    fn zoom(&mut self, camera: &mut Camera, input: &ControllerInput, dt: f32) {
+        let target = self.zoom_target.get_or_insert(camera.zoom);
+        if input.zoom != 0.0 {
+            *target = (*target * (-self.zoom_speed * input.zoom).exp())
+                .clamp(self.min_zoom, self.max_zoom);
+        }
+        let target = *target;
+
+        let before = camera.zoom;
+        let t = 1.0 - (-self.zoom_smoothing * dt).exp();
+        camera.zoom += (target - before) * t;
+        if (target - camera.zoom).abs() < 1e-4 {
+            camera.zoom = target;
+        }
+
+        if let (true, Some(cursor)) = (self.zoom_to_cursor, input.cursor) {
+            // the visible half height at the target is `MARGIN * zoom` in both
+            // projections, keep the point under the cursor where it is
+            let right = screen_right(camera);
+            let up = right.cross(-camera.rot).normalize();
+            let offset = right * cursor.x * camera.aspect - up * cursor.y;
+            camera.target += offset * MARGIN * (before - camera.zoom);
+            if camera.zoom > before && self.max_zoom > before {
+                // zooming out brings the pivot back to the center by `max_zoom`
+                let left = (self.max_zoom - camera.zoom) / (self.max_zoom - before);
+                camera.target *= left.clamp(0.0, 1.0);
+            }
+            camera.target = camera.target.clamp_length_max(self.pivot_radius);
+        }
+    }
+}
+
+fn screen_right(camera: &Camera) -> Vec3 {
+    (-camera.rot).cross(camera.up).normalize()
+}
+
+fn trackball(camera: &mut Camera, angle: Vec2) {
+    let right = screen_right(camera);
+    let up = right.cross(-camera.rot).normalize();
+    // the direction the eye moves in, the scene follows the cursor
+    let motion = -right * angle.x + up * angle.y;
+    let axis = camera.rot.cross(motion);
+    if axis.length_squared() == 0.0 {
+        return;
+    }
+    let rot = Quat::from_axis_angle(axis.normalize(), motion.length());
+    camera.rot = rot.mul_vec3(camera.rot).normalize();
+    camera.up = rot.mul_vec3(camera.up).normalize();
+}
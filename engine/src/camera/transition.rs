@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, ops::RangeInclusive, time::Duration};
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::{Camera, MARGIN};
+
+/// Everything needed to put a camera back where it was.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub target: Vec3,
+    pub rot: Vec3,
+    pub up: Vec3,
+    pub zoom: f32,
+    pub perspective: bool,
+}
+
+impl CameraState {
+    pub fn of(camera: &Camera, perspective: bool) -> Self {
+        Self {
+            target: camera.target,
+            rot: camera.rot,
+            up: camera.up,
+            zoom: camera.zoom,
+            perspective,
+        }
+    }
+
+    /// Moves `camera` here and returns the perspective flag to render with.
+    pub fn apply(&self, camera: &mut Camera) -> bool {
+        camera.target = self.target;
+        camera.rot = self.rot;
+        camera.up = self.up;
+        camera.zoom = self.zoom;
+        self.perspective
+    }
+
+    /// Directions are interpolated along the sphere and zoom in log space, the
+    /// projection switches halfway.
+    pub fn interpolate(&self, to: &CameraState, t: f32) -> CameraState {
+        let rot = slerp(self.rot, to.rot, t);
+        let up = slerp(self.up, to.up, t);
+        // keep up perpendicular to the view direction
+        let up = (up - rot * up.dot(rot)).normalize_or(to.up);
+        CameraState {
+            target: self.target.lerp(to.target, t),
+            rot,
+            up,
+            zoom: (self.zoom.ln() + (to.zoom.ln() - self.zoom.ln()) * t).exp(),
+            perspective: if t < 0.5 {
+                self.perspective
+            } else {
+                to.perspective
+            },
+        }
+    }
+
+    /// Looks at the bounding sphere of `points` along `direction`, pointing
+    /// from the points to the camera. The zoom stays in `zoom_range`, the
+    /// controller's limits, so it doesn't snap after the transition.
+    pub fn framing(
+        camera: &Camera,
+        perspective: bool,
+        points: impl IntoIterator<Item = Vec3>,
+        direction: Vec3,
+        zoom_range: RangeInclusive<f32>,
+    ) -> Option<CameraState> {
+        let points: Vec<Vec3> = points.into_iter().collect();
+        if points.is_empty() {
+            return None;
+        }
+        let center = points.iter().fold(Vec3::ZERO, |sum, &p| sum + p) / points.len() as f32;
+        let radius = points
+            .iter()
+            .map(|&p| (p - center).length())
+            .fold(0.0, f32::max);
+
+        let rot = direction.normalize_or(camera.rot);
+        let up = (camera.up - rot * camera.up.dot(rot)).normalize_or(camera.up);
+        // the visible half height at the target is `MARGIN * zoom`
+        let fit = radius / (MARGIN * camera.aspect.min(1.0));
+        Some(CameraState {
+            target: center,
+            rot,
+            up,
+            zoom: fit.clamp(*zoom_range.start(), *zoom_range.end()),
+            perspective,
+        })
+    }
+}
+
+trait NormalizeOr {
+    fn normalize_or(self, fallback: Self) -> Self;
+}
+
+impl NormalizeOr for Vec3 {
+    fn normalize_or(self, fallback: Self) -> Self {
+        let length = self.length();
+        if length > 1e-6 {
+            self / length
+        } else {
+            fallback
+        }
+    }
+}
+
+fn slerp(from: Vec3, to: Vec3, t: f32) -> Vec3 {
+    let dot = from.dot(to).clamp(-1.0, 1.0);
+    if dot > 0.9995 {
+        return from.lerp(to, t).normalize();
+    }
+    let angle = dot.acos();
+    let sin = angle.sin();
+    if sin.abs() < 1e-6 {
+        // opposite directions, turn around any perpendicular axis
+        let axis = from.any_orthonormal_vector();
+        return glam::Quat::from_axis_angle(axis, angle * t).mul_vec3(from);
+    }
+    (from * ((1.0 - t) * angle).sin() + to * (t * angle).sin()) / sin
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    SmoothStep,
+    EaseInOutCubic,
+    EaseOutExpo,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::EaseOutExpo => {
+                if t >= 1.0 {
+                    1.0
+                } else {
+                    1.0 - 2f32.powf(-10.0 * t)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraTransition {
+    from: CameraState,
+    to: CameraState,
+    duration: Duration,
+    elapsed: Duration,
+    easing: Easing,
+}
+
+impl CameraTransition {
+    pub fn new(from: CameraState, to: CameraState, duration: Duration, easing: Easing) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            elapsed: Duration::from_secs(0),
+            easing,
+        }
+    }
+
+    pub fn target(&self) -> &CameraState {
+        &self.to
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Advances by `delta` and returns where the camera should be now.
+    pub fn update(&mut self, delta: Duration) -> CameraState {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+        if self.duration == Duration::from_secs(0) {
+            return self.to;
+        }
+        let t = self.elapsed.as_secs_f32() / self.duration.as_secs_f32();
+        self.from.interpolate(&self.to, self.easing.apply(t))
+    }
+}
+
+/// Named camera states, serialized as a map.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bookmarks {
+    bookmarks: BTreeMap<String, CameraState>,
+}
+
+impl Bookmarks {
+    pub fn save(&mut self, name: impl Into<String>, state: CameraState) {
+        self.bookmarks.insert(name.into(), state);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CameraState> {
+        self.bookmarks.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<CameraState> {
+        self.bookmarks.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CameraState)> {
+        self.bookmarks.iter().map(|(n, s)| (n.as_str(), s))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+    }
+}
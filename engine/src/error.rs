@@ -0,0 +1,132 @@
+use std::{fmt, path::PathBuf};
+
+/// Reasons the engine can fail to start.
+#[derive(Debug)]
+pub enum StartupError {
+    /// The window couldn't be created.
+    Window(winit::error::OsError),
+    /// No backend and power preference produced an adapter for the window.
+    NoAdapter,
+    /// Every adapter that was found refused to create a device.
+    NoDevice { tried: Vec<String> },
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::Window(e) => write!(f, "failed to create window: {}", e),
+            StartupError::NoAdapter => write!(
+                f,
+                "no graphics adapter supports this window, run with --list-adapters for details"
+            ),
+            StartupError::NoDevice { tried } => write!(
+                f,
+                "no graphics adapter could create a device (tried {})",
+                tried.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StartupError::Window(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<winit::error::OsError> for StartupError {
+    fn from(e: winit::error::OsError) -> Self {
+        StartupError::Window(e)
+    }
+}
+
+/// Reasons an image file can't be loaded at runtime.
+#[derive(Debug)]
+pub enum ImageLoadError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    /// The directory has no PNG, JPEG or TGA files.
+    Empty(PathBuf),
+    /// Layers of an array all need the size of the first.
+    SizeMismatch {
+        path: PathBuf,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    Texture(TextureError),
+}
+
+impl fmt::Display for ImageLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageLoadError::Io(e) => write!(f, "failed to read image: {}", e),
+            ImageLoadError::Image(e) => write!(f, "failed to decode image: {}", e),
+            ImageLoadError::Empty(path) => write!(f, "no images in {}", path.display()),
+            ImageLoadError::SizeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} is {}x{}, expected {}x{}",
+                path.display(),
+                found.0,
+                found.1,
+                expected.0,
+                expected.1
+            ),
+            ImageLoadError::Texture(e) => write!(f, "failed to create texture: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageLoadError::Io(e) => Some(e),
+            ImageLoadError::Image(e) => Some(e),
+            ImageLoadError::Texture(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageLoadError {
+    fn from(e: std::io::Error) -> Self {
+        ImageLoadError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for ImageLoadError {
+    fn from(e: image::ImageError) -> Self {
+        ImageLoadError::Image(e)
+    }
+}
+
+impl From<TextureError> for ImageLoadError {
+    fn from(e: TextureError) -> Self {
+        ImageLoadError::Texture(e)
+    }
+}
+
+/// Texture descriptors wgpu would only reject once the texture is bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureError {
+    /// Comparison samplers only sample depth formats.
+    CompareNonDepth(wgpu::TextureFormat),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::CompareNonDepth(format) => {
+                write!(f, "comparison sampler on non-depth format {:?}", format)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
@@ -1,14 +1,21 @@
 use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
 use winit::event::{
-    DeviceEvent, DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton,
+    AxisId, ButtonId, DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton,
     MouseScrollDelta, TouchPhase,
 };
 
-use crate::Size;
-#[derive(Debug)]
+use crate::{windows::WindowId, Size};
+
+/// Device ids are platform handles that can't be restored from a recording.
+fn dummy_device() -> DeviceId {
+    unsafe { DeviceId::dummy() }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum RunnerEvent {
-    Window(WindowEvent),
+    Window(WindowId, WindowEvent),
     Device(DeviceEvent),
     RenderComplete {
         frame_time: Duration,
@@ -17,7 +24,7 @@ pub enum RunnerEvent {
     None,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum WindowEvent {
     Resized(Size),
     Moved((i32, i32)),
@@ -25,27 +32,33 @@ pub enum WindowEvent {
     Destroyed,
     Focused(bool),
     KeyboardInput {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
         input: KeyboardInput,
         is_synthetic: bool,
     },
     ModifiersChanged(ModifiersState),
     CursorMoved {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
         position: (f64, f64),
     },
     CursorEntered {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
     },
     CursorLeft {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
     },
     MouseWheel {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
         delta: MouseScrollDelta,
         phase: TouchPhase,
     },
     MouseInput {
+        #[serde(skip, default = "dummy_device")]
         device_id: DeviceId,
         state: ElementState,
         button: MouseButton,
@@ -122,3 +135,46 @@ impl From<winit::event::WindowEvent<'_>> for WindowEvent {
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeviceEvent {
+    Added,
+    Removed,
+    MouseMotion {
+        delta: (f64, f64),
+    },
+    MouseWheel {
+        delta: MouseScrollDelta,
+    },
+    Motion {
+        axis: AxisId,
+        value: f64,
+    },
+    Button {
+        button: ButtonId,
+        state: ElementState,
+    },
+    Key(KeyboardInput),
+    Text {
+        codepoint: char,
+    },
+}
+
+impl From<winit::event::DeviceEvent> for DeviceEvent {
+    fn from(event: winit::event::DeviceEvent) -> Self {
+        match event {
+            winit::event::DeviceEvent::Added => DeviceEvent::Added,
+            winit::event::DeviceEvent::Removed => DeviceEvent::Removed,
+            winit::event::DeviceEvent::MouseMotion { delta } => DeviceEvent::MouseMotion { delta },
+            winit::event::DeviceEvent::MouseWheel { delta } => DeviceEvent::MouseWheel { delta },
+            winit::event::DeviceEvent::Motion { axis, value } => {
+                DeviceEvent::Motion { axis, value }
+            }
+            winit::event::DeviceEvent::Button { button, state } => {
+                DeviceEvent::Button { button, state }
+            }
+            winit::event::DeviceEvent::Key(input) => DeviceEvent::Key(input),
+            winit::event::DeviceEvent::Text { codepoint } => DeviceEvent::Text { codepoint },
+        }
+    }
+}
@@ -1,3 +1,9 @@
+pub mod binding;
+pub mod cache;
 pub mod common;
+pub mod debug;
+pub mod graph;
 pub mod helper;
+pub mod mipmap;
+pub mod picking;
 pub mod texture;
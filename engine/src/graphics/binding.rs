@@ -0,0 +1,228 @@
+use std::{fmt::Display, sync::Arc};
+
+use super::{cache::pipelines, texture::Texture};
+
+/// Describes a bind group entry by entry, bindings are numbered in the order
+/// they are added. Layouts come from the pipeline cache, so groups of the
+/// same shape share one.
+#[derive(Debug, Clone, Default)]
+pub struct BindGroupBuilder {
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl BindGroupBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entry(mut self, visibility: wgpu::ShaderStage, ty: wgpu::BindingType) -> Self {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding: self.entries.len() as u32,
+            visibility,
+            ty,
+            count: None,
+        });
+        self
+    }
+
+    pub fn uniform(self, visibility: wgpu::ShaderStage) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        )
+    }
+
+    /// A uniform picked per draw with a dynamic offset, `size` is the size
+    /// of one value.
+    pub fn dynamic_uniform(self, visibility: wgpu::ShaderStage, size: wgpu::BufferSize) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: Some(size),
+            },
+        )
+    }
+
+    pub fn storage(self, visibility: wgpu::ShaderStage, read_only: bool) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        )
+    }
+
+    /// A texture shaped like `texture`, see [`Texture::sample_type`].
+    pub fn texture(self, visibility: wgpu::ShaderStage, texture: &Texture) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Texture {
+                multisampled: texture.samples > 1,
+                view_dimension: texture.view_dimension,
+                sample_type: texture.sample_type(),
+            },
+        )
+    }
+
+    pub fn storage_texture(
+        self,
+        visibility: wgpu::ShaderStage,
+        format: wgpu::TextureFormat,
+        view_dimension: wgpu::TextureViewDimension,
+        access: wgpu::StorageTextureAccess,
+    ) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension,
+            },
+        )
+    }
+
+    /// A sampler matching the one `texture` was created with.
+    pub fn sampler(self, visibility: wgpu::ShaderStage, texture: &Texture) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Sampler {
+                filtering: texture.sampler_desc.filtering(),
+                comparison: texture.sampler_desc.comparison(),
+            },
+        )
+    }
+
+    /// [`Self::texture`] followed by [`Self::sampler`].
+    pub fn sampled_texture(self, visibility: wgpu::ShaderStage, texture: &Texture) -> Self {
+        self.texture(visibility, texture)
+            .sampler(visibility, texture)
+    }
+
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
+    pub fn layout(
+        &self,
+        device: &wgpu::Device,
+        name: Option<impl Display>,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let label = name.as_ref().map(|l| format!("{}_layout", l));
+        pipelines()
+            .lock()
+            .bind_group_layout(device, &self.entries, label.as_deref())
+    }
+
+    /// Creates the layout and a group of `resources`, one per entry.
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        resources: &[BindResource],
+        name: Option<impl Display>,
+    ) -> Bindings {
+        debug_assert_eq!(resources.len(), self.entries.len());
+        let label = name.as_ref().map(|l| l.to_string());
+        let layout = self.layout(device, label.as_ref());
+        Bindings::new(device, layout, resources, label)
+    }
+}
+
+/// A resource for one entry of a [`BindGroupBuilder`].
+#[derive(Debug, Clone, Copy)]
+pub enum BindResource<'a> {
+    Buffer(&'a wgpu::Buffer),
+    /// `size` of `None` binds the rest of the buffer.
+    BufferRange {
+        buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: Option<wgpu::BufferSize>,
+    },
+    Texture(&'a Texture),
+    View(&'a wgpu::TextureView),
+    Sampler(&'a wgpu::Sampler),
+}
+
+impl<'a> BindResource<'a> {
+    fn resource(self) -> wgpu::BindingResource<'a> {
+        match self {
+            BindResource::Buffer(buffer) => wgpu::BindingResource::Buffer {
+                buffer,
+                offset: 0,
+                size: None,
+            },
+            BindResource::BufferRange {
+                buffer,
+                offset,
+                size,
+            } => wgpu::BindingResource::Buffer {
+                buffer,
+                offset,
+                size,
+            },
+            BindResource::Texture(texture) => wgpu::BindingResource::TextureView(&texture.view),
+            BindResource::View(view) => wgpu::BindingResource::TextureView(view),
+            BindResource::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        }
+    }
+}
+
+/// A bind group and its layout, built by [`BindGroupBuilder::build`].
+#[derive(Debug)]
+pub struct Bindings {
+    pub layout: Arc<wgpu::BindGroupLayout>,
+    pub binding: wgpu::BindGroup,
+    label: Option<String>,
+}
+
+impl Bindings {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: Arc<wgpu::BindGroupLayout>,
+        resources: &[BindResource],
+        label: Option<String>,
+    ) -> Self {
+        let binding = Self::create(device, &layout, resources, label.as_deref());
+        Self {
+            layout,
+            binding,
+            label,
+        }
+    }
+
+    /// Creates the group again with the same layout, after a resource was
+    /// replaced, like a resized texture or a reallocated `ItemBuffer`.
+    /// Bundles recorded with the old group keep using the old resources.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &[BindResource]) {
+        self.binding = Self::create(device, &self.layout, resources, self.label.as_deref());
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        resources: &[BindResource],
+        label: Option<&str>,
+    ) -> wgpu::BindGroup {
+        let entries: Vec<_> = resources
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.resource(),
+            })
+            .collect();
+        let label = label.map(|l| format!("{}_binding", l));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: label.as_deref(),
+            layout,
+            entries: &entries,
+        })
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, sync::Arc};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use super::common::{ColorTarget, DepthSettings, PipelineFormat, PipelineSettings};
+
+lazy_static! {
+    static ref PIPELINES: Arc<Mutex<PipelineCache>> =
+        Arc::new(Mutex::new(PipelineCache::default()));
+}
+
+pub fn pipelines() -> Arc<Mutex<PipelineCache>> {
+    Arc::clone(&PIPELINES)
+}
+
+type LayoutKey = Vec<wgpu::BindGroupLayoutEntry>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BufferKey {
+    stride: wgpu::BufferAddress,
+    step_mode: wgpu::InputStepMode,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DepthKey {
+    format: wgpu::TextureFormat,
+    write: bool,
+    compare: wgpu::CompareFunction,
+    bias: (i32, u32, u32),
+    stencil: wgpu::StencilState,
+}
+
+impl From<&DepthSettings> for DepthKey {
+    fn from(depth: &DepthSettings) -> Self {
+        Self {
+            format: depth.format,
+            write: depth.write,
+            compare: depth.compare,
+            bias: (
+                depth.bias.constant,
+                depth.bias.slope_scale.to_bits(),
+                depth.bias.clamp.to_bits(),
+            ),
+            stencil: depth.stencil.clone(),
+        }
+    }
+}
+
+/// Everything a render pipeline is built from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    vs: String,
+    fs: String,
+    vs_entry: String,
+    fs_entry: String,
+    layouts: Vec<LayoutKey>,
+    buffers: Vec<BufferKey>,
+    format: wgpu::TextureFormat,
+    samples: u32,
+    topology: wgpu::PrimitiveTopology,
+    targets: Vec<ColorTarget>,
+    front_face: wgpu::FrontFace,
+    cull_mode: wgpu::CullMode,
+    polygon_mode: wgpu::PolygonMode,
+    depth: Option<DepthKey>,
+    alpha_to_coverage: bool,
+}
+
+impl PipelineKey {
+    fn uses_shader(&self, shader: &str) -> bool {
+        self.vs == shader || self.fs == shader
+    }
+}
+
+/// Bind group layouts, pipeline layouts and render pipelines shared between
+/// everything asking for the same description. Pipelines can only be cached
+/// when all of their bind group layouts came from [`Self::bind_group_layout`].
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    layouts: HashMap<LayoutKey, Arc<wgpu::BindGroupLayout>>,
+    /// Cached bind group layouts by address, to recognise them in
+    /// `PipelineSettings::layouts`.
+    layout_keys: HashMap<usize, LayoutKey>,
+    pipeline_layouts: HashMap<Vec<LayoutKey>, Arc<wgpu::PipelineLayout>>,
+    pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+    generation: usize,
+}
+
+impl PipelineCache {
+    pub fn bind_group_layout(
+        &mut self,
+        device: &wgpu::Device,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        label: Option<&str>,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        if let Some(layout) = self.layouts.get(entries) {
+            return Arc::clone(layout);
+        }
+        let layout = Arc::new(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label, entries }),
+        );
+        self.layout_keys
+            .insert(Arc::as_ptr(&layout) as usize, entries.to_vec());
+        self.layouts.insert(entries.to_vec(), Arc::clone(&layout));
+        layout
+    }
+
+    fn layout_keys(&self, layouts: &[&wgpu::BindGroupLayout]) -> Option<Vec<LayoutKey>> {
+        layouts
+            .iter()
+            .map(|&layout| {
+                let address = layout as *const wgpu::BindGroupLayout as usize;
+                self.layout_keys.get(&address).cloned()
+            })
+            .collect()
+    }
+
+    /// `None` when a layout in `settings` isn't cached.
+    pub fn key(
+        &self,
+        format: PipelineFormat,
+        settings: &PipelineSettings,
+        vs: &str,
+        fs: &str,
+    ) -> Option<PipelineKey> {
+        Some(PipelineKey {
+            vs: vs.to_owned(),
+            fs: fs.to_owned(),
+            vs_entry: settings.vs_entry.to_owned(),
+            fs_entry: settings.fs_entry.to_owned(),
+            layouts: self.layout_keys(settings.layouts)?,
+            buffers: settings
+                .buffers
+                .iter()
+                .map(|buffer| BufferKey {
+                    stride: buffer.array_stride,
+                    step_mode: buffer.step_mode,
+                    attributes: buffer.attributes.to_vec(),
+                })
+                .collect(),
+            format: format.format,
+            samples: settings.samples,
+            topology: settings.topology,
+            targets: settings.targets.to_vec(),
+            front_face: settings.front_face,
+            cull_mode: settings.cull_mode,
+            polygon_mode: settings.polygon_mode,
+            depth: settings.depth.as_ref().map(DepthKey::from),
+            alpha_to_coverage: settings.alpha_to_coverage,
+        })
+    }
+
+    pub fn pipeline_layout(
+        &mut self,
+        device: &wgpu::Device,
+        layouts: &[&wgpu::BindGroupLayout],
+        label: Option<&str>,
+    ) -> Arc<wgpu::PipelineLayout> {
+        let create = || {
+            Arc::new(
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label,
+                    bind_group_layouts: layouts,
+                    push_constant_ranges: &[],
+                }),
+            )
+        };
+        match self.layout_keys(layouts) {
+            Some(keys) => Arc::clone(self.pipeline_layouts.entry(keys).or_insert_with(create)),
+            None => create(),
+        }
+    }
+
+    pub fn get(&self, key: &PipelineKey) -> Option<Arc<wgpu::RenderPipeline>> {
+        self.pipelines.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: PipelineKey, pipeline: Arc<wgpu::RenderPipeline>) {
+        self.pipelines.insert(key, pipeline);
+    }
+
+    /// Drops the pipelines built from `shader` after it was reloaded.
+    pub fn purge_shader(&mut self, shader: &str) {
+        self.pipelines.retain(|key, _| !key.uses_shader(shader));
+        self.generation += 1;
+    }
+
+    /// Drops everything, for a new device.
+    pub fn purge(&mut self) {
+        self.layouts.clear();
+        self.layout_keys.clear();
+        self.pipeline_layouts.clear();
+        self.pipelines.clear();
+        self.generation += 1;
+    }
+
+    /// Changes on every purge, pipelines taken from the cache before then
+    /// should be rebuilt.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+}
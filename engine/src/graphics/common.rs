@@ -1,16 +1,21 @@
 use std::{
     convert::TryInto,
+    fmt::Display,
+    ops::Range,
     sync::{atomic::AtomicUsize, Arc},
 };
 
 use crevice::std140::Std140;
 use parking_lot::{RwLock, RwLockReadGuard};
+use serde::{Deserialize, Serialize};
 use wgpu::SwapChainDescriptor;
 use winit::dpi::PhysicalSize;
 
-use crate::graphics::helper::create_buffer_size;
+use crate::graphics::{
+    binding::BindGroupBuilder, cache::pipelines, helper::create_buffer_size, texture::Texture,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Size {
     pub width: u32,
     pub height: u32,
@@ -34,6 +39,16 @@ pub struct PipelineSettings<'a> {
     pub buffers: &'a [wgpu::VertexBufferLayout<'a>],
     pub topology: wgpu::PrimitiveTopology,
     pub samples: u32,
+    /// One entry per color attachment, in the order the pass declares them.
+    pub targets: &'a [ColorTarget],
+    pub front_face: wgpu::FrontFace,
+    pub cull_mode: wgpu::CullMode,
+    pub polygon_mode: wgpu::PolygonMode,
+    /// `None` for pipelines used in passes without a depth attachment.
+    pub depth: Option<DepthSettings>,
+    pub alpha_to_coverage: bool,
+    pub vs_entry: &'a str,
+    pub fs_entry: &'a str,
 }
 
 impl Default for PipelineSettings<'_> {
@@ -43,6 +58,118 @@ impl Default for PipelineSettings<'_> {
             buffers: &[],
             topology: wgpu::PrimitiveTopology::TriangleList,
             samples: 0,
+            targets: &[ColorTarget::PRIMARY],
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth: Some(DepthSettings::default()),
+            alpha_to_coverage: false,
+            vs_entry: "main",
+            fs_entry: "main",
+        }
+    }
+}
+
+/// Blending for one color target.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Blend {
+    pub color: wgpu::BlendState,
+    pub alpha: wgpu::BlendState,
+    pub write_mask: wgpu::ColorWrite,
+}
+
+impl Blend {
+    pub const REPLACE: Self = Self {
+        color: wgpu::BlendState::REPLACE,
+        alpha: wgpu::BlendState::REPLACE,
+        write_mask: wgpu::ColorWrite::ALL,
+    };
+
+    /// Straight alpha, for transparent surfaces.
+    pub const ALPHA: Self = Self {
+        color: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::SrcAlpha,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        },
+        write_mask: wgpu::ColorWrite::ALL,
+    };
+
+    pub const ADDITIVE: Self = Self {
+        color: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::SrcAlpha,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        write_mask: wgpu::ColorWrite::ALL,
+    };
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Self::REPLACE
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ColorTarget {
+    /// `None` uses the format the pipeline is built for.
+    pub format: Option<wgpu::TextureFormat>,
+    pub blend: Blend,
+}
+
+impl ColorTarget {
+    pub const PRIMARY: Self = Self {
+        format: None,
+        blend: Blend::REPLACE,
+    };
+
+    pub fn blended(blend: Blend) -> Self {
+        Self {
+            format: None,
+            blend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthSettings {
+    pub format: wgpu::TextureFormat,
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+    pub bias: wgpu::DepthBiasState,
+    pub stencil: wgpu::StencilState,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            format: Texture::DEPTH_FORMAT,
+            write: true,
+            compare: wgpu::CompareFunction::Less,
+            bias: wgpu::DepthBiasState::default(),
+            stencil: wgpu::StencilState::default(),
+        }
+    }
+}
+
+impl DepthSettings {
+    /// Tests against the depth buffer without writing to it, for overlays
+    /// and transparent surfaces.
+    pub fn read_only() -> Self {
+        Self {
+            write: false,
+            ..Self::default()
         }
     }
 }
@@ -60,13 +187,38 @@ impl From<&SwapChainDescriptor> for PipelineFormat {
     }
 }
 
+/// A uniform buffer holding one `T`, for pipelines that bind it in their own group.
+#[derive(Debug)]
+pub struct UniformBuffer<T>
+where
+    T: crevice::std140::AsStd140,
+{
+    pub buffer: wgpu::Buffer,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T> UniformBuffer<T>
+where
+    T: crevice::std140::AsStd140,
+{
+    pub fn new(buffer: wgpu::Buffer) -> Self {
+        Self {
+            buffer,
+            _t: std::marker::PhantomData,
+        }
+    }
+    pub fn update(&self, queue: &wgpu::Queue, data: T) {
+        queue.write_buffer(&self.buffer, 0, data.as_std140().as_bytes())
+    }
+}
+
 #[derive(Debug)]
 pub struct UniformBinding<T>
 where
     T: crevice::std140::AsStd140,
 {
     pub buffer: wgpu::Buffer,
-    pub layout: wgpu::BindGroupLayout,
+    pub layout: Arc<wgpu::BindGroupLayout>,
     pub binding: wgpu::BindGroup,
     _t: std::marker::PhantomData<T>,
 }
@@ -77,7 +229,7 @@ where
 {
     pub fn new(
         buffer: wgpu::Buffer,
-        layout: wgpu::BindGroupLayout,
+        layout: Arc<wgpu::BindGroupLayout>,
         binding: wgpu::BindGroup,
     ) -> Self {
         Self {
@@ -92,6 +244,141 @@ where
     }
 }
 
+/// Many `T`s in one uniform buffer, bound once and picked per draw with a
+/// dynamic offset. Values sit [`wgpu::BIND_BUFFER_ALIGNMENT`] apart, the
+/// offset alignment every adapter supports.
+#[derive(Debug)]
+pub struct DynamicUniformBuffer<T>
+where
+    T: crevice::std140::AsStd140,
+{
+    pub buffer: wgpu::Buffer,
+    pub layout: Arc<wgpu::BindGroupLayout>,
+    pub binding: wgpu::BindGroup,
+    /// Values pushed since the last clear, already padded to the stride.
+    staged: Vec<u8>,
+    capacity: usize,
+    generation: usize,
+    label: Option<String>,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T> DynamicUniformBuffer<T>
+where
+    T: crevice::std140::AsStd140,
+{
+    pub fn new(device: &wgpu::Device, capacity: usize, name: Option<impl Display>) -> Self {
+        let label = name.map(|l| format!("{}_dynamic_uniform", l));
+        let layout = BindGroupBuilder::new()
+            .dynamic_uniform(
+                wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                Self::size(),
+            )
+            .layout(device, label.as_ref());
+        let capacity = capacity.max(1);
+        let (buffer, binding) = Self::create(device, &layout, capacity, label.as_deref());
+        Self {
+            buffer,
+            layout,
+            binding,
+            staged: Vec::new(),
+            capacity,
+            generation: 0,
+            label,
+            _t: std::marker::PhantomData,
+        }
+    }
+
+    fn size() -> wgpu::BufferSize {
+        wgpu::BufferSize::new(T::std140_size_static() as u64).expect("uniform of size 0")
+    }
+
+    /// Bytes between two values.
+    pub fn stride() -> wgpu::BufferAddress {
+        let align = wgpu::BIND_BUFFER_ALIGNMENT;
+        (T::std140_size_static() as wgpu::BufferAddress + align - 1) / align * align
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        capacity: usize,
+        label: Option<&str>,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: Self::stride() * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: Some(Self::size()),
+                },
+            }],
+        });
+        (buffer, binding)
+    }
+
+    /// Forgets the values of the previous frame, offsets start at 0 again.
+    pub fn clear(&mut self) {
+        self.staged.clear();
+    }
+
+    /// Stages `value` for the next [`Self::write`] and returns the offset to
+    /// draw it with.
+    pub fn push(&mut self, value: T) -> wgpu::DynamicOffset {
+        let offset = self.staged.len();
+        self.staged.extend_from_slice(value.as_std140().as_bytes());
+        self.staged.resize(offset + Self::stride() as usize, 0);
+        offset as wgpu::DynamicOffset
+    }
+
+    pub fn len(&self) -> usize {
+        self.staged.len() / Self::stride() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// Values that fit before the buffer has to grow.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Uploads the staged values. When they don't fit the buffer and bind
+    /// group are recreated with headroom, and `true` is returned, anything
+    /// that recorded the old bind group has to be rebuilt.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let len = self.len();
+        let grown = len > self.capacity;
+        if grown {
+            self.capacity = len + len / 2;
+            let (buffer, binding) =
+                Self::create(device, &self.layout, self.capacity, self.label.as_deref());
+            self.buffer = buffer;
+            self.binding = binding;
+            self.generation += 1;
+        }
+        if !self.staged.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.staged);
+        }
+        grown
+    }
+
+    /// Changes whenever the bind group is recreated.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
 #[derive(Debug)]
 pub struct TextureBinding {
     pub layout: TextureLayout,
@@ -106,13 +393,15 @@ impl TextureBinding {
 
 #[derive(Debug)]
 pub struct TextureLayout {
-    pub layout: wgpu::BindGroupLayout,
+    pub layout: Arc<wgpu::BindGroupLayout>,
 }
 
 #[derive(Debug)]
 pub struct ItemBufferInner {
     buffer: RwLock<wgpu::Buffer>,
     num_items: AtomicUsize,
+    /// Items the buffer has room for, at least `num_items`.
+    capacity: AtomicUsize,
     generation: AtomicUsize,
     label: Option<String>,
     usage: wgpu::BufferUsage,
@@ -141,6 +430,7 @@ where
             inner: Arc::new(ItemBufferInner {
                 buffer: RwLock::new(buffer),
                 num_items: AtomicUsize::new(num_items),
+                capacity: AtomicUsize::new(num_items),
                 generation: AtomicUsize::default(),
                 label: label.map(|s| s.as_ref().to_owned()),
                 usage,
@@ -149,30 +439,106 @@ where
         }
     }
 
+    /// Replaces the contents with `data`. The buffer is only reallocated when
+    /// `data` doesn't fit or uses less than a quarter of it, with room to grow.
     pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) {
-        let num_items = self
-            .inner
-            .num_items
-            .load(std::sync::atomic::Ordering::Acquire);
-        if data.len() > num_items || data.len() < num_items / 2 {
+        let capacity = self.capacity();
+        if data.len() > capacity || data.len() < capacity / 4 {
+            let capacity = (data.len() + data.len() / 2).max(1);
             let buffer = create_buffer_size::<T, _>(
                 device,
-                data.len(),
+                capacity,
                 self.inner.usage,
                 self.inner.label.as_ref(),
             );
             let mut lock = self.inner.buffer.write();
             *lock = buffer;
+            self.inner
+                .capacity
+                .store(capacity, std::sync::atomic::Ordering::SeqCst);
+            // bundles recorded over the old buffer are stale even if the
+            // number of items stays the same
             self.inner
                 .generation
                 .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         }
-        self.inner
-            .num_items
-            .store(data.len(), std::sync::atomic::Ordering::SeqCst);
+        self.set_num_items(data.len());
         queue.write_buffer(&self.inner.buffer.read(), 0, bytemuck::cast_slice(data));
     }
 
+    /// Overwrites the items from `offset`, growing `num_items` when writing
+    /// past the end. Panics if the range doesn't fit the capacity.
+    pub fn write_range(&self, queue: &wgpu::Queue, offset: usize, data: &[T]) {
+        let end = offset + data.len();
+        assert!(
+            end <= self.capacity(),
+            "write to {}..{} past capacity {}",
+            offset,
+            end,
+            self.capacity()
+        );
+        queue.write_buffer(
+            &self.inner.buffer.read(),
+            Self::byte_offset(offset),
+            bytemuck::cast_slice(data),
+        );
+        self.set_num_items(self.num_items().max(end));
+    }
+
+    /// Writes every range in `dirty` from `data`, the full contents.
+    pub fn write_dirty(&self, queue: &wgpu::Queue, dirty: &mut DirtyRanges, data: &[T]) {
+        for range in dirty.take() {
+            let range = range.start.min(data.len())..range.end.min(data.len());
+            self.write_range(queue, range.start, &data[range]);
+        }
+    }
+
+    /// Uploads through a staging belt instead of the queue, for data that is
+    /// rewritten every frame. The belt has to be finished before `encoder` is
+    /// submitted and recalled after.
+    pub fn write_staged(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut wgpu::util::StagingBelt,
+        offset: usize,
+        data: &[T],
+    ) {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let size = match wgpu::BufferSize::new(bytes.len() as wgpu::BufferAddress) {
+            Some(size) => size,
+            None => return,
+        };
+        let end = offset + data.len();
+        assert!(end <= self.capacity(), "staged write past capacity");
+        belt.write_buffer(
+            encoder,
+            &self.inner.buffer.read(),
+            Self::byte_offset(offset),
+            size,
+            device,
+        )
+        .copy_from_slice(bytes);
+        self.set_num_items(self.num_items().max(end));
+    }
+
+    fn byte_offset(offset: usize) -> wgpu::BufferAddress {
+        (offset * std::mem::size_of::<T>()) as wgpu::BufferAddress
+    }
+
+    /// Bundles draw `num_items`, so a new count counts as a new buffer.
+    fn set_num_items(&self, num_items: usize) {
+        let old = self
+            .inner
+            .num_items
+            .swap(num_items, std::sync::atomic::Ordering::SeqCst);
+        if old != num_items {
+            self.inner
+                .generation
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
     pub fn buffer(&self) -> RwLockReadGuard<wgpu::Buffer> {
         self.inner.buffer.read()
     }
@@ -183,6 +549,12 @@ where
             .load(std::sync::atomic::Ordering::Acquire)
     }
 
+    pub fn capacity(&self) -> usize {
+        self.inner
+            .capacity
+            .load(std::sync::atomic::Ordering::Acquire)
+    }
+
     pub fn id(&self) -> usize {
         self.inner
             .generation
@@ -190,11 +562,160 @@ where
     }
 }
 
+/// Item ranges waiting to be uploaded. Ranges that overlap or are at most
+/// `gap` items apart are written together.
+#[derive(Debug, Clone, Default)]
+pub struct DirtyRanges {
+    ranges: Vec<Range<usize>>,
+    gap: usize,
+}
+
+impl DirtyRanges {
+    pub fn new(gap: usize) -> Self {
+        Self {
+            ranges: Vec::new(),
+            gap,
+        }
+    }
+
+    pub fn mark(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.ranges.push(range);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The coalesced ranges in order, leaving nothing marked.
+    pub fn take(&mut self) -> Vec<Range<usize>> {
+        let mut ranges = std::mem::take(&mut self.ranges);
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end + self.gap => {
+                    last.end = last.end.max(range.end)
+                }
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
 pub trait BundleData {
     type Data;
+    /// Changes whenever a bundle recorded from [`Self::buffers`] is stale,
+    /// like when a buffer is reallocated or the number of items changes.
     type Id: PartialEq + Default;
     fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, ico: &Self::Data);
     fn id(&self) -> Self::Id;
+    /// The buffers to bind and the draws to record over them.
+    fn buffers(&self) -> BundleBuffers<'_>;
+}
+
+/// One draw call of a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Draw {
+    Vertices {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    /// Needs [`BundleBuffers::index`].
+    Indexed {
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    },
+}
+
+impl Draw {
+    pub fn vertices(vertices: Range<u32>) -> Self {
+        Self::instanced(vertices, 0..1)
+    }
+
+    pub fn instanced(vertices: Range<u32>, instances: Range<u32>) -> Self {
+        Draw::Vertices {
+            vertices,
+            instances,
+        }
+    }
+
+    pub fn indexed(indices: Range<u32>, instances: Range<u32>) -> Self {
+        Draw::Indexed {
+            indices,
+            base_vertex: 0,
+            instances,
+        }
+    }
+}
+
+/// What a bundle draws from, see [`BundleData::buffers`].
+pub struct BundleBuffers<'a> {
+    /// Bound to slots in order, per-vertex and per-instance buffers alike, so
+    /// they line up with `PipelineSettings::buffers`.
+    pub vertex: Vec<RwLockReadGuard<'a, wgpu::Buffer>>,
+    pub index: Option<(RwLockReadGuard<'a, wgpu::Buffer>, wgpu::IndexFormat)>,
+    pub draws: Vec<Draw>,
+}
+
+impl<'a> BundleBuffers<'a> {
+    /// A single draw of every vertex in `buffer`.
+    pub fn vertices<T>(buffer: &'a ItemBuffer<T>) -> Self
+    where
+        T: bytemuck::Pod,
+    {
+        Self {
+            vertex: vec![buffer.buffer()],
+            index: None,
+            draws: vec![Draw::vertices(0..buffer.num_items() as u32)],
+        }
+    }
+
+    /// Every vertex of `vertices` for every item of `instances`, bound to
+    /// slots 0 and 1.
+    pub fn instanced<V, I>(vertices: &'a ItemBuffer<V>, instances: &'a ItemBuffer<I>) -> Self
+    where
+        V: bytemuck::Pod,
+        I: bytemuck::Pod,
+    {
+        Self {
+            vertex: vec![vertices.buffer(), instances.buffer()],
+            index: None,
+            draws: vec![Draw::instanced(
+                0..vertices.num_items() as u32,
+                0..instances.num_items() as u32,
+            )],
+        }
+    }
+
+    /// Binds the buffers and records the draws.
+    pub fn record(&'a self, bundle: &mut wgpu::RenderBundleEncoder<'a>) {
+        for (slot, buffer) in self.vertex.iter().enumerate() {
+            bundle.set_vertex_buffer(slot as u32, buffer.slice(..));
+        }
+        if let Some((buffer, format)) = &self.index {
+            bundle.set_index_buffer(buffer.slice(..), *format);
+        }
+        for draw in &self.draws {
+            match draw.clone() {
+                Draw::Vertices {
+                    vertices,
+                    instances,
+                } => bundle.draw(vertices, instances),
+                Draw::Indexed {
+                    indices,
+                    base_vertex,
+                    instances,
+                } => {
+                    debug_assert!(self.index.is_some(), "indexed draw without indices");
+                    bundle.draw_indexed(indices, base_vertex, instances)
+                }
+            }
+        }
+    }
 }
 
 pub enum RendererInvalid {
@@ -212,7 +733,7 @@ pub trait Pipeline {
         device: &wgpu::Device,
         format: PipelineFormat,
         samples: u32,
-    ) -> wgpu::RenderPipeline;
+    ) -> Arc<wgpu::RenderPipeline>;
 
     fn build_bundle(
         &self,
@@ -228,10 +749,12 @@ pub struct Renderer<P>
 where
     P: Pipeline,
 {
-    pub pipeline: wgpu::RenderPipeline,
+    pub pipeline: Arc<wgpu::RenderPipeline>,
     pub bundle: wgpu::RenderBundle,
     pipeline_valid: bool,
     bundle_valid: bool,
+    /// Cache generation the pipeline was taken from.
+    generation: usize,
     pub format: PipelineFormat,
     pub renderer: P,
     pub data: P::Data,
@@ -250,6 +773,7 @@ where
         data: P::Data,
     ) -> Self {
         let renderer = P::build(device, settings);
+        let generation = pipelines().lock().generation();
         let pipeline = renderer.build_pipeline(device, format, samples);
         let bundle = renderer.build_bundle(device, &pipeline, format, samples, &data);
         let id = Default::default();
@@ -258,6 +782,7 @@ where
             bundle,
             pipeline_valid: true,
             bundle_valid: true,
+            generation,
             renderer,
             format,
             data,
@@ -271,6 +796,11 @@ where
             self.id = id;
             self.bundle_valid = false;
         }
+        let generation = pipelines().lock().generation();
+        if self.generation != generation {
+            self.generation = generation;
+            self.pipeline_valid = false;
+        }
         if !self.pipeline_valid {
             self.pipeline = self.renderer.build_pipeline(device, self.format, samples);
             self.pipeline_valid = true;
@@ -297,16 +827,72 @@ where
 }
 
 impl ItemBuffer<u32> {
-    pub async fn mapped_read(&self, device: &wgpu::Device, mut offset: wgpu::BufferAddress) -> u32 {
-        let bits = (offset as usize % 2) * 4;
-        offset = (offset / 2) * 4;
+    /// Reads the item at `index`, waiting for the GPU. Prefer
+    /// [`crate::graphics::picking::Picker`] for reads every frame.
+    pub async fn mapped_read(&self, device: &wgpu::Device, index: wgpu::BufferAddress) -> u32 {
+        // mapped ranges start at multiples of 8 bytes, two items
+        let offset = (index / 2) * 8;
+        let at = (index as usize % 2) * 4;
 
         let buffer = self.buffer();
         let buffer_slice = buffer.slice(offset..offset + 8);
         let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
         device.poll(wgpu::Maintain::Wait);
         mapping.await.unwrap();
-        let data = buffer_slice.get_mapped_range();
-        u32::from_le_bytes(data[bits..bits + 4].try_into().unwrap())
+        let value = {
+            let data = buffer_slice.get_mapped_range();
+            u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+        };
+        buffer.unmap();
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device on any adapter, `None` on machines without one.
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let adapter =
+            futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                compatible_surface: None,
+            }))?;
+        futures::executor::block_on(adapter.request_device(&Default::default(), None)).ok()
+    }
+
+    fn item_buffer(device: &wgpu::Device, items: usize) -> ItemBuffer<u32> {
+        let usage = wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST;
+        let buffer = create_buffer_size::<u32, _>(device, items, usage, Some("test"));
+        ItemBuffer::new(buffer, items, usage, Some("test"))
+    }
+
+    #[test]
+    fn growing_changes_generation() {
+        let (device, queue) = match device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut buffer = item_buffer(&device, 4);
+        let id = buffer.id();
+        buffer.update(&device, &queue, &[0; 8]);
+        assert!(buffer.capacity() >= 8);
+        assert_ne!(buffer.id(), id);
+    }
+
+    #[test]
+    fn rewriting_in_place_keeps_generation() {
+        let (device, queue) = match device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut buffer = item_buffer(&device, 4);
+        let id = buffer.id();
+        buffer.update(&device, &queue, &[1; 4]);
+        buffer.write_range(&queue, 1, &[2; 2]);
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(buffer.id(), id);
     }
 }
@@ -0,0 +1,400 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crevice::std140::AsStd140;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use super::{
+    cache::pipelines,
+    common::{DepthSettings, PipelineFormat, PipelineSettings, UniformBinding},
+    helper::{create_pipeline, create_uniform_binding},
+};
+use crate::Size;
+
+lazy_static! {
+    static ref DEBUG_DRAW: Arc<Mutex<HashMap<&'static str, DebugCommands>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+/// Debug drawings by layer, see [`submit`].
+pub fn debug_draw() -> Arc<Mutex<HashMap<&'static str, DebugCommands>>> {
+    Arc::clone(&DEBUG_DRAW)
+}
+
+/// Replaces what `layer` draws until the next submit to it or [`clear`].
+/// Works from any thread, drawings stay on screen however often a layer is
+/// submitted.
+pub fn submit(layer: &'static str, commands: DebugCommands) {
+    DEBUG_DRAW.lock().insert(layer, commands);
+}
+
+pub fn clear(layer: &str) {
+    DEBUG_DRAW.lock().remove(layer);
+}
+
+pub mod colors {
+    use glam::Vec4;
+
+    pub const RED: Vec4 = glam::const_vec4!([1.0, 0.0, 0.0, 1.0]);
+    pub const GREEN: Vec4 = glam::const_vec4!([0.0, 1.0, 0.0, 1.0]);
+    pub const BLUE: Vec4 = glam::const_vec4!([0.0, 0.0, 1.0, 1.0]);
+    pub const YELLOW: Vec4 = glam::const_vec4!([1.0, 1.0, 0.0, 1.0]);
+    pub const WHITE: Vec4 = glam::const_vec4!([1.0, 1.0, 1.0, 1.0]);
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl DebugVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float4,
+                },
+            ],
+        }
+    }
+}
+
+/// Text at a point in the world, the UI draws it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMarker {
+    pub position: Vec3,
+    pub text: String,
+    pub color: Vec4,
+}
+
+/// Segments of the circles of [`DebugCommands::sphere`].
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// A batch of debug shapes in world space, built on any thread and handed
+/// to [`submit`]. Colors are linear RGBA.
+#[derive(Debug, Clone)]
+pub struct DebugCommands {
+    /// Line ends in pairs, hidden behind the scene.
+    depth: Vec<DebugVertex>,
+    /// Line ends in pairs, drawn over the scene.
+    overlay: Vec<DebugVertex>,
+    markers: Vec<TextMarker>,
+    depth_test: bool,
+}
+
+impl Default for DebugCommands {
+    fn default() -> Self {
+        Self {
+            depth: Vec::new(),
+            overlay: Vec::new(),
+            markers: Vec::new(),
+            depth_test: true,
+        }
+    }
+}
+
+impl DebugCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the shapes added after this are hidden behind the scene, on
+    /// by default.
+    pub fn depth_test(&mut self, depth_test: bool) -> &mut Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Vec4) -> &mut Self {
+        let lines = if self.depth_test {
+            &mut self.depth
+        } else {
+            &mut self.overlay
+        };
+        lines.extend_from_slice(&[
+            DebugVertex {
+                position: from.into(),
+                color: color.into(),
+            },
+            DebugVertex {
+                position: to.into(),
+                color: color.into(),
+            },
+        ]);
+        self
+    }
+
+    /// A line with a head at `to`, a fifth of its length.
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec4) -> &mut Self {
+        let direction = to - from;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            return self;
+        }
+        let head = length * 0.2;
+        let back = to - direction / length * head;
+        let (a, b) = (direction / length).any_orthonormal_pair();
+        self.line(from, to, color);
+        for side in [a, -a, b, -b].iter() {
+            self.line(to, back + *side * head * 0.5, color);
+        }
+        self
+    }
+
+    /// A cross of three lines `size` long.
+    pub fn point(&mut self, position: Vec3, size: f32, color: Vec4) -> &mut Self {
+        let half = size / 2.0;
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z].iter() {
+            self.line(position - *axis * half, position + *axis * half, color);
+        }
+        self
+    }
+
+    /// Circles around the three axes.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) -> &mut Self {
+        let axes = [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)];
+        for &(u, v) in axes.iter() {
+            let at = |i: usize| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0..CIRCLE_SEGMENTS {
+                self.line(at(i), at(i + 1), color);
+            }
+        }
+        self
+    }
+
+    /// The x, y and z axes of `transform` in red, green and blue.
+    pub fn axes(&mut self, transform: Mat4, size: f32) -> &mut Self {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        let axes = [
+            (Vec3::X, colors::RED),
+            (Vec3::Y, colors::GREEN),
+            (Vec3::Z, colors::BLUE),
+        ];
+        for &(axis, color) in axes.iter() {
+            let end = transform.transform_point3(axis * size);
+            self.arrow(origin, end, color);
+        }
+        self
+    }
+
+    /// Text starting at `position`, always drawn over the scene.
+    pub fn text(&mut self, position: Vec3, text: impl Into<String>, color: Vec4) -> &mut Self {
+        self.markers.push(TextMarker {
+            position,
+            text: text.into(),
+            color,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth.is_empty() && self.overlay.is_empty() && self.markers.is_empty()
+    }
+}
+
+/// A [`TextMarker`] projected to the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenMarker {
+    /// Physical pixels from the top left, divide by the window's scale
+    /// factor for logical ones.
+    pub position: Vec2,
+    pub text: String,
+    pub color: Vec4,
+}
+
+/// The text markers of every layer in front of the camera, for the UI to
+/// draw. `size` is the physical size of the target.
+pub fn screen_markers(view_proj: Mat4, size: Size) -> Vec<ScreenMarker> {
+    let layers = DEBUG_DRAW.lock();
+    layers
+        .values()
+        .flat_map(|commands| commands.markers.iter())
+        .filter_map(|marker| {
+            let clip = view_proj * marker.position.extend(1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.truncate() / clip.w;
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || !(0.0..=1.0).contains(&ndc.z) {
+                return None;
+            }
+            Some(ScreenMarker {
+                position: glam::vec2(
+                    (ndc.x + 1.0) / 2.0 * size.width as f32,
+                    (1.0 - ndc.y) / 2.0 * size.height as f32,
+                ),
+                text: marker.text.clone(),
+                color: marker.color,
+            })
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsStd140)]
+pub struct DebugUniform {
+    pub view_proj: mint::ColumnMatrix4<f32>,
+}
+
+/// Draws the lines of every layer in a pass of its own, batched into one
+/// vertex buffer per frame. The pass loads the scene's color and depth.
+pub struct DebugRenderer {
+    uniform: UniformBinding<DebugUniform>,
+    buffer: wgpu::Buffer,
+    /// Vertices the buffer has room for.
+    capacity: usize,
+    depth_vertices: u32,
+    overlay_vertices: u32,
+    depth_pipeline: Arc<wgpu::RenderPipeline>,
+    overlay_pipeline: Arc<wgpu::RenderPipeline>,
+    format: PipelineFormat,
+    samples: u32,
+    /// Cache generation the pipelines were taken from.
+    generation: usize,
+}
+
+impl DebugRenderer {
+    pub fn new(device: &wgpu::Device, format: impl Into<PipelineFormat>, samples: u32) -> Self {
+        let format = format.into();
+        let uniform = create_uniform_binding(device, Some("debug"));
+        let capacity = 1024;
+        let generation = pipelines().lock().generation();
+        let (depth_pipeline, overlay_pipeline) =
+            Self::build_pipelines(device, &uniform, format, samples);
+        Self {
+            buffer: Self::create_buffer(device, capacity),
+            uniform,
+            capacity,
+            depth_vertices: 0,
+            overlay_vertices: 0,
+            depth_pipeline,
+            overlay_pipeline,
+            format,
+            samples,
+            generation,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_vertices"),
+            size: (capacity * std::mem::size_of::<DebugVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn build_pipelines(
+        device: &wgpu::Device,
+        uniform: &UniformBinding<DebugUniform>,
+        format: PipelineFormat,
+        samples: u32,
+    ) -> (Arc<wgpu::RenderPipeline>, Arc<wgpu::RenderPipeline>) {
+        let build = |compare| {
+            let settings = PipelineSettings {
+                layouts: &[&uniform.layout],
+                buffers: &[DebugVertex::desc()],
+                topology: wgpu::PrimitiveTopology::LineList,
+                samples,
+                cull_mode: wgpu::CullMode::None,
+                depth: Some(DepthSettings {
+                    compare,
+                    ..DepthSettings::read_only()
+                }),
+                ..Default::default()
+            };
+            create_pipeline(
+                device,
+                format,
+                &settings,
+                "shader.debug.vert",
+                "shader.debug.frag",
+                Some("debug"),
+            )
+        };
+        (
+            build(wgpu::CompareFunction::LessEqual),
+            build(wgpu::CompareFunction::Always),
+        )
+    }
+
+    /// Uploads the lines of every layer, call once a frame before the debug
+    /// pass. `samples` has to match the pass.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_proj: Mat4,
+        samples: u32,
+    ) {
+        let generation = pipelines().lock().generation();
+        if self.samples != samples || self.generation != generation {
+            let (depth_pipeline, overlay_pipeline) =
+                Self::build_pipelines(device, &self.uniform, self.format, samples);
+            self.depth_pipeline = depth_pipeline;
+            self.overlay_pipeline = overlay_pipeline;
+            self.samples = samples;
+            self.generation = generation;
+        }
+        self.uniform.update(
+            queue,
+            DebugUniform {
+                view_proj: view_proj.into(),
+            },
+        );
+
+        let mut vertices = Vec::new();
+        {
+            let layers = DEBUG_DRAW.lock();
+            for commands in layers.values() {
+                vertices.extend_from_slice(&commands.depth);
+            }
+            self.depth_vertices = vertices.len() as u32;
+            for commands in layers.values() {
+                vertices.extend_from_slice(&commands.overlay);
+            }
+            self.overlay_vertices = vertices.len() as u32 - self.depth_vertices;
+        }
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len() + vertices.len() / 2;
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    /// Records the lines uploaded by the last [`Self::prepare`].
+    pub fn draw<'p>(&'p self, pass: &mut wgpu::RenderPass<'p>) {
+        let depth = self.depth_vertices;
+        let end = depth + self.overlay_vertices;
+        if end == 0 {
+            return;
+        }
+        pass.set_bind_group(0, &self.uniform.binding, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        if depth > 0 {
+            pass.set_pipeline(&self.depth_pipeline);
+            pass.draw(0..depth, 0..1);
+        }
+        if end > depth {
+            pass.set_pipeline(&self.overlay_pipeline);
+            pass.draw(depth..end, 0..1);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::Size;
+
+use super::texture::{Texture, TextureDescriptor};
+
+/// The swap chain frame passed to [`RenderGraph::execute`].
+pub const BACKBUFFER: &str = "backbuffer";
+
+/// A texture owned by the graph, sized to the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetDesc {
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsage,
+}
+
+impl TargetDesc {
+    pub fn color(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        }
+    }
+
+    pub fn depth() -> Self {
+        Self {
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        }
+    }
+
+    pub fn with_usage(mut self, usage: wgpu::TextureUsage) -> Self {
+        self.usage |= usage;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorAttachment {
+    pub target: &'static str,
+    pub load: wgpu::LoadOp<wgpu::Color>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthAttachment {
+    pub target: &'static str,
+    pub load: wgpu::LoadOp<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    /// Records into a render pass over the declared attachments.
+    Render,
+    /// Records straight into the command encoder, for copies.
+    Commands,
+}
+
+/// A pass and the resources it touches. Attachments count as writes, and as
+/// reads too when they are loaded instead of cleared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassDesc {
+    pub name: &'static str,
+    pub kind: PassKind,
+    pub color: Vec<ColorAttachment>,
+    pub depth: Option<DepthAttachment>,
+    /// With more than one sample the color attachments are rendered into
+    /// multisampled copies and resolved into the declared targets.
+    pub samples: u32,
+    pub reads: Vec<&'static str>,
+    pub writes: Vec<&'static str>,
+}
+
+impl PassDesc {
+    pub fn render(name: &'static str) -> Self {
+        Self::new(name, PassKind::Render)
+    }
+
+    pub fn commands(name: &'static str) -> Self {
+        Self::new(name, PassKind::Commands)
+    }
+
+    fn new(name: &'static str, kind: PassKind) -> Self {
+        Self {
+            name,
+            kind,
+            color: Vec::new(),
+            depth: None,
+            samples: 1,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn color(mut self, target: &'static str, load: wgpu::LoadOp<wgpu::Color>) -> Self {
+        self.color.push(ColorAttachment { target, load });
+        self
+    }
+
+    pub fn depth(mut self, target: &'static str, load: wgpu::LoadOp<f32>) -> Self {
+        self.depth = Some(DepthAttachment { target, load });
+        self
+    }
+
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn read(mut self, resource: &'static str) -> Self {
+        self.reads.push(resource);
+        self
+    }
+
+    pub fn write(mut self, resource: &'static str) -> Self {
+        self.writes.push(resource);
+        self
+    }
+
+    fn reads(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let color = self
+            .color
+            .iter()
+            .filter(|a| a.load == wgpu::LoadOp::Load)
+            .map(|a| a.target);
+        let depth = self
+            .depth
+            .iter()
+            .filter(|a| a.load == wgpu::LoadOp::Load)
+            .map(|a| a.target);
+        self.reads.iter().copied().chain(color).chain(depth)
+    }
+
+    fn writes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let color = self.color.iter().map(|a| a.target);
+        let depth = self.depth.iter().map(|a| a.target);
+        self.writes.iter().copied().chain(color).chain(depth)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// An attachment names no declared target, only color may use the
+    /// backbuffer.
+    UnknownTarget {
+        pass: &'static str,
+        target: &'static str,
+    },
+    UnknownPass(String),
+    /// The passes left over once nothing else could be ordered.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::UnknownTarget { pass, target } => {
+                write!(f, "pass {} uses undeclared target {}", pass, target)
+            }
+            GraphError::UnknownPass(pass) => write!(f, "no pass named {}", pass),
+            GraphError::Cycle(passes) => {
+                write!(
+                    f,
+                    "render passes depend on each other: {}",
+                    passes.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// What a pass records into, see [`PassKind`].
+pub enum PassEncoder<'r, 'p> {
+    Render(&'r mut wgpu::RenderPass<'p>),
+    Commands(&'p mut wgpu::CommandEncoder),
+}
+
+/// Passes declared up front and recorded every frame in dependency order.
+/// Passes whose writes never reach the backbuffer or an output are culled,
+/// and the transient targets they need are allocated for the window size.
+pub struct RenderGraph {
+    format: wgpu::TextureFormat,
+    size: Size,
+    targets: BTreeMap<&'static str, TargetDesc>,
+    passes: Vec<PassDesc>,
+    outputs: BTreeSet<&'static str>,
+
+    order: Vec<usize>,
+    textures: BTreeMap<(&'static str, u32), Texture>,
+}
+
+impl RenderGraph {
+    pub fn new(sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        let mut outputs = BTreeSet::new();
+        outputs.insert(BACKBUFFER);
+        Self {
+            format: sc_desc.format,
+            size: Size::new(sc_desc.width, sc_desc.height),
+            targets: BTreeMap::new(),
+            passes: Vec::new(),
+            outputs,
+            order: Vec::new(),
+            textures: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_target(&mut self, name: &'static str, desc: TargetDesc) -> &mut Self {
+        self.targets.insert(name, desc);
+        self
+    }
+
+    pub fn add_pass(&mut self, pass: PassDesc) -> &mut Self {
+        self.passes.push(pass);
+        self
+    }
+
+    /// Keeps the passes writing `resource` alive, for resources outside the
+    /// graph such as readback buffers.
+    pub fn add_output(&mut self, resource: &'static str) -> &mut Self {
+        self.outputs.insert(resource);
+        self
+    }
+
+    /// Orders and culls the passes and allocates their targets.
+    pub fn compile(&mut self, device: &wgpu::Device) -> Result<(), GraphError> {
+        for pass in &self.passes {
+            let color = pass
+                .color
+                .iter()
+                .map(|a| a.target)
+                .filter(|&target| target != BACKBUFFER);
+            for target in color.chain(pass.depth.iter().map(|a| a.target)) {
+                if !self.targets.contains_key(target) {
+                    return Err(GraphError::UnknownTarget {
+                        pass: pass.name,
+                        target,
+                    });
+                }
+            }
+        }
+
+        let live = self.live();
+        self.order = self.sort(&live)?;
+        self.allocate(device);
+        Ok(())
+    }
+
+    /// Passes contributing to an output, walking back from the last pass.
+    fn live(&self) -> Vec<bool> {
+        let mut needed = self.outputs.clone();
+        let mut live = vec![false; self.passes.len()];
+        loop {
+            let mut changed = false;
+            for (i, pass) in self.passes.iter().enumerate().rev() {
+                if !live[i] && pass.writes().any(|w| needed.contains(w)) {
+                    live[i] = true;
+                    needed.extend(pass.reads());
+                    changed = true;
+                }
+            }
+            if !changed {
+                return live;
+            }
+        }
+    }
+
+    /// Passes sharing a resource run in the order they were added, unless one
+    /// reads something no earlier pass writes, then it waits for the writers.
+    fn sort(&self, live: &[bool]) -> Result<Vec<usize>, GraphError> {
+        let passes: Vec<usize> = (0..self.passes.len()).filter(|&i| live[i]).collect();
+        let early = |reader: usize, resource: &str| {
+            !passes
+                .iter()
+                .take_while(|&&k| k < reader)
+                .any(|&k| self.passes[k].writes().any(|w| w == resource))
+        };
+        let mut after: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for &i in &passes {
+            for &j in &passes {
+                if i == j {
+                    continue;
+                }
+                let (a, b) = (&self.passes[i], &self.passes[j]);
+                let reads = b
+                    .reads()
+                    .any(|r| a.writes().any(|w| w == r) && (i < j || early(j, r)));
+                let overwrites = i < j
+                    && b.writes().any(|w| {
+                        a.writes()
+                            .chain(a.reads().filter(|&r| !early(i, r)))
+                            .any(|r| r == w)
+                    });
+                if reads || overwrites {
+                    after.entry(j).or_default().insert(i);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(passes.len());
+        let mut left = passes;
+        while !left.is_empty() {
+            let next = left.iter().position(|j| {
+                after
+                    .get(j)
+                    .map_or(true, |deps| deps.iter().all(|d| order.contains(d)))
+            });
+            match next {
+                Some(index) => order.push(left.remove(index)),
+                None => {
+                    return Err(GraphError::Cycle(
+                        left.iter().map(|&i| self.passes[i].name).collect(),
+                    ))
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    fn allocate(&mut self, device: &wgpu::Device) {
+        let mut needed = BTreeMap::new();
+        for &i in &self.order {
+            let pass = &self.passes[i];
+            for attachment in &pass.color {
+                let desc = self.targets.get(attachment.target).copied();
+                if let Some(desc) = desc {
+                    needed.insert((attachment.target, 1), desc);
+                }
+                if pass.samples > 1 {
+                    let format = desc.map_or(self.format, |d| d.format);
+                    needed.insert((attachment.target, pass.samples), TargetDesc::color(format));
+                }
+            }
+            if let Some(depth) = &pass.depth {
+                needed.insert((depth.target, pass.samples), self.targets[depth.target]);
+            }
+        }
+
+        let size = wgpu::Extent3d {
+            width: self.size.width,
+            height: self.size.height,
+            depth: 1,
+        };
+        self.textures = needed
+            .into_iter()
+            .map(|((name, samples), desc)| {
+                let texture = Texture::create_texture(
+                    device,
+                    &TextureDescriptor {
+                        size,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: desc.format,
+                        usage: desc.usage,
+                        samples,
+                        levels: 1,
+                        sampler: Default::default(),
+                    },
+                    Some(format!("{}_x{}", name, samples)),
+                )
+                .expect("graph targets use the default sampler");
+                ((name, samples), texture)
+            })
+            .collect();
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: Size) {
+        self.size = size;
+        self.allocate(device);
+    }
+
+    /// Changes the sample count of a render pass and reallocates its targets.
+    pub fn set_samples(
+        &mut self,
+        device: &wgpu::Device,
+        pass: &str,
+        samples: u32,
+    ) -> Result<(), GraphError> {
+        let desc = self
+            .passes
+            .iter_mut()
+            .find(|p| p.name == pass)
+            .ok_or_else(|| GraphError::UnknownPass(pass.to_owned()))?;
+        desc.samples = samples.max(1);
+        self.allocate(device);
+        Ok(())
+    }
+
+    /// The single sampled texture allocated for `name`.
+    pub fn target(&self, name: &str) -> Option<&Texture> {
+        self.textures
+            .iter()
+            .find(|((n, samples), _)| *n == name && *samples == 1)
+            .map(|(_, texture)| texture)
+    }
+
+    /// Passes that survived culling, in the order they are recorded.
+    pub fn order(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.order.iter().map(move |&i| self.passes[i].name)
+    }
+
+    /// Records every live pass. `record` is called once per pass with its
+    /// name and gets `context` back for the lifetime of the pass, so bundles
+    /// borrowed from it can be executed.
+    pub fn execute<C>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &wgpu::TextureView,
+        context: &mut C,
+        mut record: impl for<'r, 'p> FnMut(&'p mut C, &'static str, PassEncoder<'r, 'p>),
+    ) {
+        for &i in &self.order {
+            let pass = &self.passes[i];
+            match pass.kind {
+                PassKind::Commands => {
+                    record(
+                        &mut *context,
+                        pass.name,
+                        PassEncoder::Commands(&mut *encoder),
+                    );
+                }
+                PassKind::Render => {
+                    let view = |target: &'static str, samples: u32| {
+                        if target == BACKBUFFER && samples == 1 {
+                            frame
+                        } else {
+                            &self.textures[&(target, samples)].view
+                        }
+                    };
+                    let color: Vec<_> = pass
+                        .color
+                        .iter()
+                        .map(|a| {
+                            let (attachment, resolve_target) = if pass.samples > 1 {
+                                (view(a.target, pass.samples), Some(view(a.target, 1)))
+                            } else {
+                                (view(a.target, 1), None)
+                            };
+                            wgpu::RenderPassColorAttachmentDescriptor {
+                                attachment,
+                                resolve_target,
+                                ops: wgpu::Operations {
+                                    load: a.load,
+                                    store: true,
+                                },
+                            }
+                        })
+                        .collect();
+                    let depth = pass.depth.as_ref().map(|a| {
+                        wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                            attachment: view(a.target, pass.samples),
+                            depth_ops: Some(wgpu::Operations {
+                                load: a.load,
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        }
+                    });
+
+                    let label = format!("{}_render_pass", pass.name);
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&label),
+                        color_attachments: &color,
+                        depth_stencil_attachment: depth,
+                    });
+                    record(
+                        &mut *context,
+                        pass.name,
+                        PassEncoder::Render(&mut render_pass),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(passes: Vec<PassDesc>) -> Vec<&'static str> {
+        let mut graph = RenderGraph::new(&wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 1,
+            height: 1,
+            present_mode: wgpu::PresentMode::Fifo,
+        });
+        graph.add_output("picks");
+        for pass in passes {
+            graph.add_pass(pass);
+        }
+        let order = graph.sort(&graph.live()).unwrap();
+        order.into_iter().map(|i| graph.passes[i].name).collect()
+    }
+
+    #[test]
+    fn shared_depth_keeps_add_order() {
+        let passes = vec![
+            PassDesc::render("main")
+                .color(BACKBUFFER, wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+                .depth("depth", wgpu::LoadOp::Clear(1.0)),
+            PassDesc::render("debug")
+                .color(BACKBUFFER, wgpu::LoadOp::Load)
+                .depth("depth", wgpu::LoadOp::Load),
+            PassDesc::render("select")
+                .color("select", wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+                .depth("depth", wgpu::LoadOp::Clear(1.0)),
+            PassDesc::commands("pick").read("select").write("picks"),
+        ];
+        assert_eq!(order(passes), ["main", "debug", "select", "pick"]);
+    }
+
+    #[test]
+    fn readers_wait_for_later_writers() {
+        let passes = vec![
+            PassDesc::commands("pick").read("select").write("picks"),
+            PassDesc::render("select").color("select", wgpu::LoadOp::Clear(wgpu::Color::BLACK)),
+        ];
+        assert_eq!(order(passes), ["select", "pick"]);
+    }
+}
@@ -1,10 +1,14 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
 use palette::rgb::{Rgb, RgbStandard};
 use wgpu::{util::DeviceExt, CommandEncoder, RenderPass, TextureView};
 
 use crate::graphics::{
-    common::{ItemBuffer, PipelineFormat, PipelineSettings, TextureLayout, UniformBinding},
+    binding::BindGroupBuilder,
+    cache::pipelines,
+    common::{
+        ItemBuffer, PipelineFormat, PipelineSettings, TextureLayout, UniformBinding, UniformBuffer,
+    },
     texture::Texture,
 };
 
@@ -58,102 +62,134 @@ pub fn create_pipeline(
     vs: &'static str,
     fs: &'static str,
     name: Option<impl Display>,
-) -> wgpu::RenderPipeline {
+) -> Arc<wgpu::RenderPipeline> {
+    let format = format.into();
+    let cache = pipelines();
+    let mut cache = cache.lock();
+    let key = cache.key(format, settings, vs, fs);
+    if let Some(pipeline) = key.as_ref().and_then(|key| cache.get(key)) {
+        return pipeline;
+    }
+
     let PipelineSettings {
         layouts,
         buffers,
         topology,
         samples,
+        targets,
+        front_face,
+        cull_mode,
+        polygon_mode,
+        depth,
+        alpha_to_coverage,
+        vs_entry,
+        fs_entry,
     } = settings;
-    let format = format.into();
 
     let label = name.as_ref().map(|l| format!("{}_render_layout", l));
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: label.as_deref(),
-        bind_group_layouts: layouts,
-        push_constant_ranges: &[],
-    });
+    let render_pipeline_layout = cache.pipeline_layout(device, layouts, label.as_deref());
 
     let shaders = crate::shaders();
     let lock = shaders.lock();
     let vs_module = lock.get(vs).unwrap();
     let fs_module = lock.get(fs).unwrap();
 
+    let targets: Vec<_> = targets
+        .iter()
+        .map(|target| wgpu::ColorTargetState {
+            format: target.format.unwrap_or(format.format),
+            color_blend: target.blend.color.clone(),
+            alpha_blend: target.blend.alpha.clone(),
+            write_mask: target.blend.write_mask,
+        })
+        .collect();
+
+    // line and point modes are an optional feature
+    let polygon_mode = if *polygon_mode != wgpu::PolygonMode::Fill
+        && !device
+            .features()
+            .contains(wgpu::Features::NON_FILL_POLYGON_MODE)
+    {
+        log::warn!("{:?} polygons aren't supported, filling them", polygon_mode);
+        wgpu::PolygonMode::Fill
+    } else {
+        *polygon_mode
+    };
+
     let label = name.as_ref().map(|l| format!("{}_render_pipeline", l));
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: label.as_deref(),
-        layout: Some(&render_pipeline_layout),
+        layout: Some(&*render_pipeline_layout),
         vertex: wgpu::VertexState {
             module: &vs_module,
-            entry_point: "main",
+            entry_point: vs_entry,
             buffers,
         },
         fragment: Some(wgpu::FragmentState {
             module: &fs_module,
-            entry_point: "main",
-            targets: &[wgpu::ColorTargetState {
-                format: format.format,
-                color_blend: wgpu::BlendState::REPLACE,
-                alpha_blend: wgpu::BlendState::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
+            entry_point: fs_entry,
+            targets: &targets,
         }),
         primitive: wgpu::PrimitiveState {
             topology: *topology,
             strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: wgpu::CullMode::Back,
-            polygon_mode: wgpu::PolygonMode::Fill,
+            front_face: *front_face,
+            cull_mode: *cull_mode,
+            polygon_mode,
         },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            bias: wgpu::DepthBiasState {
-                constant: 0,
-                slope_scale: 0.0,
-                clamp: 0.0,
-            },
+        depth_stencil: depth.as_ref().map(|depth| wgpu::DepthStencilState {
+            bias: depth.bias.clone(),
             clamp_depth: false,
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
+            format: depth.format,
+            depth_write_enabled: depth.write,
+            depth_compare: depth.compare,
+            stencil: depth.stencil.clone(),
         }),
         multisample: wgpu::MultisampleState {
             count: *samples,
             mask: !0,
-            alpha_to_coverage_enabled: false,
+            alpha_to_coverage_enabled: *alpha_to_coverage,
         },
-    })
+    });
+    let pipeline = Arc::new(pipeline);
+
+    match key {
+        Some(key) => cache.insert(key, Arc::clone(&pipeline)),
+        None => log::debug!("not caching {:?}, its layouts aren't cached", label),
+    }
+    pipeline
 }
 
-pub fn create_uniform_binding<T>(
+pub fn create_uniform_buffer<T>(
     device: &wgpu::Device,
     name: Option<impl Display>,
-) -> UniformBinding<T>
+) -> UniformBuffer<T>
 where
     T: crevice::std140::AsStd140,
 {
-    let label = name.as_ref().map(|l| format!("{}_uniform_layout", l));
-    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-        label: label.as_deref(),
-    });
-
-    let label = name.as_ref().map(|l| format!("{}_unfiform_buffer", l));
+    let label = name.map(|l| format!("{}_uniform_buffer", l));
     let buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: label.as_deref(),
         size: T::std140_size_static() as wgpu::BufferAddress,
         usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         mapped_at_creation: false,
     });
+    UniformBuffer::new(buffer)
+}
+
+pub fn create_uniform_binding<T>(
+    device: &wgpu::Device,
+    name: Option<impl Display>,
+) -> UniformBinding<T>
+where
+    T: crevice::std140::AsStd140,
+{
+    let label = name.as_ref().map(|l| format!("{}_uniform", l));
+    let layout = BindGroupBuilder::new()
+        .uniform(wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT)
+        .layout(device, label);
+
+    let buffer = create_uniform_buffer::<T>(device, name.as_ref()).buffer;
 
     let label = name.as_ref().map(|l| format!("{}_uniform_binding", l));
     let binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -177,31 +213,10 @@ pub fn create_texture_binding_layout(
     texture: &Texture,
     name: Option<impl Display>,
 ) -> TextureLayout {
-    let label = name.as_ref().map(|s| format!("{}_texture_binding", s));
-    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStage::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    multisampled: false,
-                    view_dimension: texture.view_dimension,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStage::FRAGMENT,
-                ty: wgpu::BindingType::Sampler {
-                    filtering: false,
-                    comparison: false,
-                },
-                count: None,
-            },
-        ],
-        label: label.as_deref(),
-    });
+    let label = name.as_ref().map(|s| format!("{}_texture", s));
+    let layout = BindGroupBuilder::new()
+        .sampled_texture(wgpu::ShaderStage::FRAGMENT, texture)
+        .layout(device, label);
 
     TextureLayout { layout }
 }
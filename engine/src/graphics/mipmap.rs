@@ -0,0 +1,187 @@
+use std::{fmt, num::NonZeroU32, sync::Arc};
+
+use super::{
+    cache::pipelines,
+    common::{PipelineFormat, PipelineSettings},
+    helper::create_pipeline,
+    texture::{SamplerDescriptor, Texture},
+};
+
+const VS: &str = "shader.blit.vert";
+const FS: &str = "shader.blit.frag";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MipError {
+    /// Levels are rendered from the one above, the texture needs these
+    /// usages.
+    MissingUsage(wgpu::TextureUsage),
+    /// Multisampled textures have no mips.
+    Multisampled(u32),
+    /// Only 2D textures and arrays of them.
+    Dimension(wgpu::TextureDimension),
+    /// Compressed, integer and depth formats can't be filtered or rendered
+    /// to.
+    Format(wgpu::TextureFormat),
+}
+
+impl fmt::Display for MipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MipError::MissingUsage(usage) => write!(f, "texture is missing usage {:?}", usage),
+            MipError::Multisampled(samples) => {
+                write!(f, "texture has {} samples, mips need 1", samples)
+            }
+            MipError::Dimension(dimension) => {
+                write!(f, "can't generate mips for {:?} textures", dimension)
+            }
+            MipError::Format(format) => write!(f, "can't generate mips for {:?}", format),
+        }
+    }
+}
+
+impl std::error::Error for MipError {}
+
+/// Fills the mip chains of textures made at runtime by down-sampling every
+/// level from the one above it with a linear blit. Sampling and rendering
+/// through views of the texture's own format keeps sRGB textures filtered
+/// in linear space.
+pub struct MipGenerator {
+    layout: Arc<wgpu::BindGroupLayout>,
+    sampler: wgpu::Sampler,
+}
+
+impl MipGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = pipelines().lock().bind_group_layout(
+            device,
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+            Some("mipmap_layout"),
+        );
+        let sampler = SamplerDescriptor::default().create(device, Some("mipmap_sampler"));
+        Self { layout, sampler }
+    }
+
+    pub fn check(texture: &Texture) -> Result<(), MipError> {
+        let usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::RENDER_ATTACHMENT;
+        if !texture.usage.contains(usage) {
+            return Err(MipError::MissingUsage(usage - texture.usage));
+        }
+        if texture.samples > 1 {
+            return Err(MipError::Multisampled(texture.samples));
+        }
+        if texture.dimension != wgpu::TextureDimension::D2 {
+            return Err(MipError::Dimension(texture.dimension));
+        }
+        let info = texture.format.describe();
+        let renderable = info
+            .guaranteed_format_features
+            .allowed_usages
+            .contains(wgpu::TextureUsage::RENDER_ATTACHMENT);
+        let filterable = info.sample_type == wgpu::TextureSampleType::Float { filterable: true };
+        if !renderable || !filterable || info.block_dimensions != (1, 1) {
+            return Err(MipError::Format(texture.format));
+        }
+        Ok(())
+    }
+
+    /// Records a pass per level below the first for every layer of
+    /// `texture`, level 0 has to be filled before the encoder is submitted.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &Texture,
+    ) -> Result<(), MipError> {
+        Self::check(texture)?;
+        if texture.levels < 2 {
+            return Ok(());
+        }
+
+        let pipeline = create_pipeline(
+            device,
+            PipelineFormat {
+                format: texture.format,
+            },
+            &PipelineSettings {
+                layouts: &[&self.layout],
+                samples: 1,
+                cull_mode: wgpu::CullMode::None,
+                depth: None,
+                ..Default::default()
+            },
+            VS,
+            FS,
+            Some("mipmap"),
+        );
+
+        for layer in 0..texture.size.depth {
+            let views: Vec<_> = (0..texture.levels)
+                .map(|level| {
+                    texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: None,
+                        format: Some(texture.format),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        aspect: wgpu::TextureAspect::All,
+                        base_mip_level: level,
+                        level_count: NonZeroU32::new(1),
+                        base_array_layer: layer,
+                        array_layer_count: NonZeroU32::new(1),
+                    })
+                })
+                .collect();
+
+            for level in views.windows(2) {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("mipmap_binding"),
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&level[0]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("mipmap_render_pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: &level[1],
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::texture::Texture;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// Ids are `R32Uint`, four bytes a pixel.
+const PIXEL: u32 = 4;
+const ROW: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+/// An id read back from the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pick {
+    /// The id under the cursor, or the closest non-zero one around it, 0 for
+    /// nothing.
+    pub id: u32,
+    pub cursor: (u32, u32),
+    /// The [`Picker::record`] call the ids were copied in.
+    pub frame: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    cursor: (u32, u32),
+    frame: u64,
+}
+
+enum Slot {
+    Free,
+    /// Copy recorded, waiting for the frame to be submitted.
+    Copied(Region),
+    Mapping(Region, MapFuture),
+}
+
+/// Reads ids under the cursor from an id target without stalling. Each frame
+/// a small region is copied into one of a ring of staging buffers, which is
+/// mapped once the frame was submitted and read a frame or two later.
+pub struct Picker {
+    slots: Vec<(wgpu::Buffer, Slot)>,
+    next: usize,
+    region: u32,
+    frame: u64,
+}
+
+impl Picker {
+    /// Widest region that fits in one aligned row.
+    pub const MAX_REGION: u32 = ROW / PIXEL;
+
+    /// `region` is the side of the square read around the cursor, clamped to
+    /// [`Self::MAX_REGION`].
+    pub fn new(device: &wgpu::Device, slots: usize, region: u32) -> Self {
+        let region = region.max(1).min(Self::MAX_REGION);
+        let slots = (0..slots.max(1))
+            .map(|i| {
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("pick_buffer_{}", i)),
+                    size: (ROW * region) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                    mapped_at_creation: false,
+                });
+                (buffer, Slot::Free)
+            })
+            .collect();
+        Self {
+            slots,
+            next: 0,
+            region,
+            frame: 0,
+        }
+    }
+
+    /// Starts mapping the copies of earlier frames and copies the region
+    /// around `cursor` from `ids`. Call once per frame while encoding, when
+    /// every slot is still in flight the frame is skipped.
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        ids: &Texture,
+        cursor: Option<(u32, u32)>,
+    ) {
+        // whatever was copied before this call has been submitted by now
+        for (buffer, slot) in self.slots.iter_mut() {
+            if let Slot::Copied(region) = *slot {
+                let mapping = buffer.slice(..).map_async(wgpu::MapMode::Read);
+                *slot = Slot::Mapping(region, Box::pin(mapping));
+            }
+        }
+
+        self.frame += 1;
+        let (cx, cy) = match cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let size = ids.size;
+        if cx >= size.width || cy >= size.height {
+            return;
+        }
+        let index = match (0..self.slots.len())
+            .map(|i| (self.next + i) % self.slots.len())
+            .find(|&i| matches!(self.slots[i].1, Slot::Free))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        self.next = (index + 1) % self.slots.len();
+
+        let width = self.region.min(size.width);
+        let height = self.region.min(size.height);
+        let region = Region {
+            x: cx.saturating_sub(width / 2).min(size.width - width),
+            y: cy.saturating_sub(height / 2).min(size.height - height),
+            width,
+            height,
+            cursor: (cx, cy),
+            frame: self.frame,
+        };
+        let (buffer, slot) = &mut self.slots[index];
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &ids.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: region.x,
+                    y: region.y,
+                    z: 0,
+                },
+            },
+            wgpu::BufferCopyView {
+                buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: ROW,
+                    rows_per_image: height,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        *slot = Slot::Copied(region);
+    }
+
+    /// The newest pick that finished mapping since the last call.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<Pick> {
+        device.poll(wgpu::Maintain::Poll);
+        let mut context = Context::from_waker(futures::task::noop_waker_ref());
+
+        let mut newest: Option<Pick> = None;
+        for (buffer, slot) in self.slots.iter_mut() {
+            let (region, result) = match slot {
+                Slot::Mapping(region, mapping) => match mapping.as_mut().poll(&mut context) {
+                    Poll::Ready(result) => (*region, result),
+                    Poll::Pending => continue,
+                },
+                _ => continue,
+            };
+            if result.is_ok() {
+                let pick = {
+                    let data = buffer.slice(..).get_mapped_range();
+                    Self::read(&data, region)
+                };
+                buffer.unmap();
+                if newest.map_or(true, |newest| pick.frame > newest.frame) {
+                    newest = Some(pick);
+                }
+            }
+            *slot = Slot::Free;
+        }
+        newest
+    }
+
+    fn read(data: &[u8], region: Region) -> Pick {
+        let (cx, cy) = region.cursor;
+        let mut closest = (u32::MAX, 0);
+        for row in 0..region.height {
+            for column in 0..region.width {
+                let at = (row * ROW + column * PIXEL) as usize;
+                let id = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+                if id == 0 {
+                    continue;
+                }
+                let dx = (region.x + column) as i64 - cx as i64;
+                let dy = (region.y + row) as i64 - cy as i64;
+                let distance = (dx * dx + dy * dy) as u32;
+                if distance < closest.0 {
+                    closest = (distance, id);
+                }
+            }
+        }
+        Pick {
+            id: closest.1,
+            cursor: region.cursor,
+            frame: region.frame,
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::Size;
+use std::{num::NonZeroU8, ops::Range};
+
+use crate::{error::TextureError, Size};
 
 use super::common::ItemBuffer;
 
@@ -14,7 +16,10 @@ pub struct Texture {
     pub usage: wgpu::TextureUsage,
     pub dimension: wgpu::TextureDimension,
     pub sampler: wgpu::Sampler,
+    pub sampler_desc: SamplerDescriptor,
     pub samples: u32,
+    /// Mip levels.
+    pub levels: u32,
     pub label: Option<String>,
 }
 
@@ -25,6 +30,147 @@ pub struct TextureDescriptor {
     pub usage: wgpu::TextureUsage,
     pub samples: u32,
     pub levels: u32,
+    pub sampler: SamplerDescriptor,
+}
+
+impl TextureDescriptor {
+    fn validate(&self) -> Result<(), TextureError> {
+        let depth = self.format.describe().sample_type == wgpu::TextureSampleType::Depth;
+        if self.sampler.comparison() && !depth {
+            return Err(TextureError::CompareNonDepth(self.format));
+        }
+        Ok(())
+    }
+}
+
+/// A `wgpu::SamplerDescriptor` without the label, kept on the [`Texture`] so
+/// binding layouts can follow it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDescriptor {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    pub compare: Option<wgpu::CompareFunction>,
+    pub anisotropy_clamp: Option<NonZeroU8>,
+    pub border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl Default for SamplerDescriptor {
+    /// Clamped and bilinear.
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: f32::MAX,
+            compare: None,
+            anisotropy_clamp: None,
+            border_color: None,
+        }
+    }
+}
+
+impl SamplerDescriptor {
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    /// Linear filtering within and between mip levels.
+    pub fn trilinear() -> Self {
+        Self {
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        }
+    }
+
+    /// Linear comparison sampler, for shadow maps.
+    pub fn compare(compare: wgpu::CompareFunction) -> Self {
+        Self {
+            compare: Some(compare),
+            ..Default::default()
+        }
+    }
+
+    /// Same mode in every direction.
+    pub fn with_address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    /// wgpu only takes 2, 4, 8 or 16, other values are rounded down to one of
+    /// those. 0 and 1 turn anisotropic filtering off.
+    pub fn with_anisotropy(mut self, clamp: u8) -> Self {
+        self.anisotropy_clamp = anisotropy_clamp(clamp);
+        if self
+            .anisotropy_clamp
+            .map_or(clamp > 1, |c| c.get() != clamp)
+        {
+            log::warn!(
+                "anisotropy clamp {} isn't supported, using {:?}",
+                clamp,
+                self.anisotropy_clamp
+            );
+        }
+        self
+    }
+
+    pub fn with_lod(mut self, lod: Range<f32>) -> Self {
+        self.lod_min_clamp = lod.start;
+        self.lod_max_clamp = lod.end;
+        self
+    }
+
+    /// Whether any of the filters interpolate, the texture has to be
+    /// filterable then.
+    pub fn filtering(&self) -> bool {
+        [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .any(|&filter| filter == wgpu::FilterMode::Linear)
+    }
+
+    pub fn comparison(&self) -> bool {
+        self.compare.is_some()
+    }
+
+    pub fn create(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            compare: self.compare,
+            anisotropy_clamp: self.anisotropy_clamp,
+            border_color: self.border_color,
+        })
+    }
+}
+
+/// The largest power of two up to 16 that is at most `clamp`.
+fn anisotropy_clamp(clamp: u8) -> Option<NonZeroU8> {
+    match clamp.min(16) {
+        0 | 1 => None,
+        clamp => NonZeroU8::new(1 << (7 - clamp.leading_zeros())),
+    }
 }
 
 impl Texture {
@@ -34,6 +180,16 @@ impl Texture {
         device: &wgpu::Device,
         desc: &TextureDescriptor,
         label: Option<impl AsRef<str>>,
+    ) -> Result<Texture, TextureError> {
+        desc.validate()?;
+        Ok(Self::create(device, desc, label))
+    }
+
+    /// [`Self::create_texture`] for descriptors that are valid by construction.
+    fn create(
+        device: &wgpu::Device,
+        desc: &TextureDescriptor,
+        label: Option<impl AsRef<str>>,
     ) -> Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: label.as_ref().map(|s| s.as_ref()),
@@ -53,15 +209,9 @@ impl Texture {
             wgpu::TextureViewDimension::D2
         };
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = desc
+            .sampler
+            .create(device, label.as_ref().map(|s| s.as_ref()));
 
         Self {
             texture,
@@ -69,7 +219,9 @@ impl Texture {
             view_dimension,
             size: desc.size,
             sampler,
+            sampler_desc: desc.sampler,
             samples: desc.samples,
+            levels: desc.levels,
             format: desc.format,
             usage: desc.usage,
             dimension: desc.dimension,
@@ -83,7 +235,8 @@ impl Texture {
         data: &[u8],
         desc: &TextureDescriptor,
         label: Option<impl AsRef<str>>,
-    ) -> Texture {
+    ) -> Result<Texture, TextureError> {
+        desc.validate()?;
         let texture = device.create_texture_with_data(
             queue,
             &wgpu::TextureDescriptor {
@@ -106,27 +259,34 @@ impl Texture {
             wgpu::TextureViewDimension::D2
         };
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = desc
+            .sampler
+            .create(device, label.as_ref().map(|s| s.as_ref()));
 
-        Self {
+        Ok(Self {
             texture,
             view,
             view_dimension,
             size: desc.size,
             sampler,
+            sampler_desc: desc.sampler,
             samples: desc.samples,
+            levels: desc.levels,
             format: desc.format,
             usage: desc.usage,
             dimension: desc.dimension,
             label: label.map(|s| s.as_ref().to_owned()),
+        })
+    }
+
+    /// How shaders see the texture, follows the format. Float textures are
+    /// only declared filterable when the sampler filters.
+    pub fn sample_type(&self) -> wgpu::TextureSampleType {
+        match self.format.describe().sample_type {
+            wgpu::TextureSampleType::Float { filterable } => wgpu::TextureSampleType::Float {
+                filterable: filterable && self.sampler_desc.filtering(),
+            },
+            sample_type => sample_type,
         }
     }
 
@@ -147,6 +307,12 @@ impl Texture {
         );
     }
 
+    /// Levels in a full mip chain down to 1x1.
+    pub fn max_levels(size: wgpu::Extent3d) -> u32 {
+        32 - size.width.max(size.height).max(1).leading_zeros()
+    }
+
+    /// Same texture at another size, mips have to be generated again.
     pub fn with_size(&self, device: &wgpu::Device, size: Size) -> Self {
         let size = wgpu::Extent3d {
             width: size.width,
@@ -154,7 +320,7 @@ impl Texture {
             depth: 1,
         };
         let label = self.label.clone();
-        Self::create_texture(
+        Self::create(
             &device,
             &TextureDescriptor {
                 size,
@@ -162,7 +328,8 @@ impl Texture {
                 format: self.format,
                 usage: self.usage,
                 samples: self.samples,
-                levels: 1,
+                levels: self.levels.min(Self::max_levels(size)),
+                sampler: self.sampler_desc,
             },
             label,
         )
@@ -170,7 +337,7 @@ impl Texture {
 
     pub fn with_samples(&mut self, device: &wgpu::Device, samples: u32) -> Self {
         let label = self.label.take();
-        Self::create_texture(
+        Self::create(
             device,
             &TextureDescriptor {
                 size: self.size,
@@ -178,7 +345,9 @@ impl Texture {
                 format: self.format,
                 usage: self.usage,
                 samples,
-                levels: 1,
+                // multisampled textures can't have mips
+                levels: if samples > 1 { 1 } else { self.levels },
+                sampler: self.sampler_desc,
             },
             label,
         )
@@ -210,7 +379,7 @@ impl Texture {
         samples: u32,
         label: Option<impl AsRef<str>>,
     ) -> Self {
-        Self::create_texture(
+        Self::create(
             device,
             &TextureDescriptor {
                 size,
@@ -219,6 +388,7 @@ impl Texture {
                 usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
                 samples,
                 levels: 1,
+                sampler: Default::default(),
             },
             label,
         )
@@ -230,7 +400,7 @@ impl Texture {
         samples: u32,
         label: Option<impl AsRef<str>>,
     ) -> Self {
-        Self::create_texture(
+        Self::create(
             device,
             &TextureDescriptor {
                 size,
@@ -239,6 +409,7 @@ impl Texture {
                 usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
                 samples,
                 levels: 1,
+                sampler: Default::default(),
             },
             label,
         )
@@ -249,7 +420,7 @@ impl Texture {
         size: wgpu::Extent3d,
         label: Option<impl AsRef<str>>,
     ) -> Self {
-        Self::create_texture(
+        Self::create(
             device,
             &TextureDescriptor {
                 size,
@@ -258,8 +429,84 @@ impl Texture {
                 usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
                 samples: 1,
                 levels: 1,
+                sampler: SamplerDescriptor::nearest(),
             },
             label,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anisotropy(clamp: u8) -> Option<u8> {
+        SamplerDescriptor::default()
+            .with_anisotropy(clamp)
+            .anisotropy_clamp
+            .map(NonZeroU8::get)
+    }
+
+    fn descriptor(format: wgpu::TextureFormat, sampler: SamplerDescriptor) -> TextureDescriptor {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED,
+            samples: 1,
+            levels: 1,
+            sampler,
+        }
+    }
+
+    #[test]
+    fn compare_needs_depth() {
+        let compare = SamplerDescriptor::compare(wgpu::CompareFunction::LessEqual);
+        assert_eq!(
+            descriptor(Texture::DEPTH_FORMAT, compare).validate(),
+            Ok(())
+        );
+        for &format in [
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureFormat::R32Float,
+            wgpu::TextureFormat::R32Uint,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                descriptor(format, compare).validate(),
+                Err(TextureError::CompareNonDepth(format))
+            );
+            assert_eq!(
+                descriptor(format, SamplerDescriptor::default()).validate(),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn anisotropy_off() {
+        assert_eq!(anisotropy(0), None);
+        assert_eq!(anisotropy(1), None);
+    }
+
+    #[test]
+    fn anisotropy_powers_of_two() {
+        for &clamp in [2, 4, 8, 16].iter() {
+            assert_eq!(anisotropy(clamp), Some(clamp));
+        }
+    }
+
+    #[test]
+    fn anisotropy_rounds_down() {
+        assert_eq!(anisotropy(3), Some(2));
+        assert_eq!(anisotropy(7), Some(4));
+        assert_eq!(anisotropy(15), Some(8));
+        assert_eq!(anisotropy(17), Some(16));
+        assert_eq!(anisotropy(u8::MAX), Some(16));
+    }
+}
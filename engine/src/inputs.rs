@@ -1,29 +1,808 @@
-use std::{collections::VecDeque, sync::Arc, thread::JoinHandle};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::Path,
+    sync::{mpsc::RecvTimeoutError, Arc},
+    time::Duration,
+};
 
-use parking_lot::{Condvar, Mutex};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+};
 
-use crate::{event::RunnerEvent, ThreadRunner};
+use crate::{
+    event::{DeviceEvent, RunnerEvent, WindowEvent},
+    lifecycle::{EngineThread, Shutdown},
+    queue::EventReceiver,
+    replay::Recorder,
+    timing::{self, Phase},
+    windows::WindowId,
+    Size, ThreadRunner,
+};
+
+pub const BINDINGS_FILE: &str = "bindings.json";
+
+/// Context that is always active.
+pub const GLOBAL: &str = "global";
+pub const QUIT: &str = "quit";
+
+const PIXELS_PER_LINE: f32 = 20.0;
+/// How often the input thread checks for shutdown while the queue is idle.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Trigger {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+}
+
+/// A trigger together with the modifiers that have to be held for it to fire.
+/// Modifiers that are not part of the chord are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Chord {
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub modifiers: ModifiersState,
+}
+
+impl Chord {
+    pub fn new(trigger: Trigger) -> Self {
+        Self {
+            trigger,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    pub fn key(key: VirtualKeyCode) -> Self {
+        Self::new(Trigger::Key(key))
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self::new(Trigger::Mouse(button))
+    }
+
+    pub fn with(mut self, modifiers: ModifiersState) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    fn matches(&self, trigger: Trigger, modifiers: ModifiersState) -> bool {
+        self.trigger == trigger && modifiers.contains(self.modifiers)
+    }
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    /// `positive` held gives `scale`, `negative` held gives `-scale`.
+    Keys {
+        negative: Chord,
+        positive: Chord,
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    /// Lines scrolled during the current tick.
+    Wheel {
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    /// Cursor movement during the current tick in normalized device coordinates.
+    CursorX {
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    CursorY {
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    /// Raw mouse movement during the current tick.
+    MouseX {
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    MouseY {
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextBindings {
+    pub actions: BTreeMap<String, Vec<Chord>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bindings {
+    pub contexts: BTreeMap<String, ContextBindings>,
+}
+
+impl Bindings {
+    /// Bindings the engine itself relies on.
+    pub fn engine() -> Self {
+        let mut bindings = Self::default();
+        bindings.action(GLOBAL, QUIT, Chord::key(VirtualKeyCode::Escape));
+        bindings
+    }
+
+    pub fn action(
+        &mut self,
+        context: impl Into<String>,
+        name: impl Into<String>,
+        chord: Chord,
+    ) -> &mut Self {
+        self.contexts
+            .entry(context.into())
+            .or_default()
+            .actions
+            .entry(name.into())
+            .or_default()
+            .push(chord);
+        self
+    }
+
+    pub fn axis(
+        &mut self,
+        context: impl Into<String>,
+        name: impl Into<String>,
+        binding: AxisBinding,
+    ) -> &mut Self {
+        self.contexts
+            .entry(context.into())
+            .or_default()
+            .axes
+            .entry(name.into())
+            .or_default()
+            .push(binding);
+        self
+    }
+
+    /// Adds the actions and axes from `other` that are not bound in `self`.
+    pub fn merge(&mut self, other: Bindings) {
+        for (name, context) in other.contexts {
+            let target = self.contexts.entry(name).or_default();
+            for (action, chords) in context.actions {
+                target.actions.entry(action).or_insert(chords);
+            }
+            for (axis, bindings) in context.axes {
+                target.axes.entry(axis).or_insert(bindings);
+            }
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Loads the bindings at `path` and fills in anything missing from
+    /// `defaults`, writing the defaults out when no file exists yet.
+    pub fn load_or_default(path: impl AsRef<Path>, defaults: Bindings) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            if let Err(e) = defaults.save(path) {
+                log::warn!("cannot write bindings to {:?}: {}", path, e);
+            }
+            return defaults;
+        }
+        match Self::load(path) {
+            Ok(mut bindings) => {
+                bindings.merge(defaults);
+                bindings
+            }
+            Err(e) => {
+                log::warn!("cannot read bindings from {:?}: {}", path, e);
+                defaults
+            }
+        }
+    }
+}
+
+/// Input held by the engine input thread, the update thread hands runners a
+/// [`InputState::snapshot`] of it every tick.
+///
+/// Edges, the mouse deltas and scrolling cover everything that happened since the
+/// previous snapshot.
+#[derive(Debug, Clone)]
+pub struct InputState {
+    /// Number of snapshots taken before this one.
+    pub tick: u64,
+    pub size: Size,
+    pub modifiers: ModifiersState,
+    pub keys: HashSet<VirtualKeyCode>,
+    pub keys_pressed: HashSet<VirtualKeyCode>,
+    pub keys_released: HashSet<VirtualKeyCode>,
+    pub buttons: HashSet<MouseButton>,
+    pub buttons_pressed: HashSet<MouseButton>,
+    pub buttons_released: HashSet<MouseButton>,
+    /// Cursor position in physical pixels, `None` while outside the window.
+    pub cursor: Option<glam::Vec2>,
+    /// Cursor movement in physical pixels.
+    pub cursor_delta: glam::Vec2,
+    /// Raw mouse movement reported by the device, unaffected by cursor acceleration.
+    pub mouse_delta: glam::Vec2,
+    /// Lines scrolled.
+    pub scroll: glam::Vec2,
+}
+
+impl InputState {
+    pub fn new(size: Size) -> Self {
+        Self {
+            tick: 0,
+            size,
+            modifiers: ModifiersState::empty(),
+            keys: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
+            buttons: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            cursor: None,
+            cursor_delta: glam::Vec2::ZERO,
+            mouse_delta: glam::Vec2::ZERO,
+            scroll: glam::Vec2::ZERO,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &RunnerEvent) {
+        match event {
+            RunnerEvent::Window(window, event) => self.handle_window_event(*window, event),
+            RunnerEvent::Device(DeviceEvent::MouseMotion { delta }) => {
+                self.mouse_delta += glam::vec2(delta.0 as f32, delta.1 as f32);
+            }
+            _ => {}
+        }
+    }
+
+    /// Cursor and size only follow the main window, keys and buttons come from
+    /// whichever window has focus.
+    fn handle_window_event(&mut self, window: WindowId, event: &WindowEvent) {
+        let main = window == WindowId::MAIN;
+        match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => set_held(
+                &mut self.keys,
+                &mut self.keys_pressed,
+                &mut self.keys_released,
+                key,
+                state,
+            ),
+            WindowEvent::MouseInput { state, button, .. } => set_held(
+                &mut self.buttons,
+                &mut self.buttons_pressed,
+                &mut self.buttons_released,
+                button,
+                state,
+            ),
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(x, y) => glam::vec2(x, y),
+                    MouseScrollDelta::PixelDelta(pos) => {
+                        glam::vec2(pos.x as f32, pos.y as f32) / PIXELS_PER_LINE
+                    }
+                };
+            }
+            WindowEvent::CursorMoved { position, .. } if main => {
+                let position = glam::vec2(position.0 as f32, position.1 as f32);
+                if let Some(last) = self.cursor {
+                    self.cursor_delta += position - last;
+                }
+                self.cursor = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } if main => self.cursor = None,
+            WindowEvent::Resized(size) | WindowEvent::ScaleFactorChanged { size, .. } if main => {
+                self.size = size
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers,
+            WindowEvent::Focused(false) => {
+                self.keys_released.extend(self.keys.drain());
+                self.buttons_released.extend(self.buttons.drain());
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => {}
+        }
+    }
+
+    /// Copies the current state and starts a new tick.
+    pub fn snapshot(&mut self) -> InputState {
+        let snapshot = self.clone();
+        self.tick += 1;
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.cursor_delta = glam::Vec2::ZERO;
+        self.mouse_delta = glam::Vec2::ZERO;
+        self.scroll = glam::Vec2::ZERO;
+        snapshot
+    }
+
+    pub fn key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Cursor position in normalized device coordinates, y pointing down.
+    /// `None` while the cursor is outside or the window is minimized.
+    pub fn cursor_ndc(&self) -> Option<glam::Vec2> {
+        self.cursor
+            .and_then(|cursor| self.to_ndc(cursor))
+            .map(|ndc| ndc - glam::Vec2::ONE)
+    }
+
+    /// Cursor movement in normalized device coordinates, zero while the window
+    /// is minimized.
+    pub fn cursor_delta_ndc(&self) -> glam::Vec2 {
+        self.to_ndc(self.cursor_delta).unwrap_or(glam::Vec2::ZERO)
+    }
+
+    /// `None` for a zero sized window, which would divide by zero.
+    fn to_ndc(&self, pixels: glam::Vec2) -> Option<glam::Vec2> {
+        if self.size.width == 0 || self.size.height == 0 {
+            return None;
+        }
+        Some(2.0 * pixels / glam::vec2(self.size.width as f32, self.size.height as f32))
+    }
+
+    fn held(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Key(key) => self.key_held(key),
+            Trigger::Mouse(button) => self.button_held(button),
+            Trigger::WheelUp | Trigger::WheelDown => false,
+        }
+    }
+
+    fn pressed(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Key(key) => self.key_pressed(key),
+            Trigger::Mouse(button) => self.button_pressed(button),
+            Trigger::WheelUp => self.scroll.y > 0.0,
+            Trigger::WheelDown => self.scroll.y < 0.0,
+        }
+    }
+
+    fn released(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Key(key) => self.key_released(key),
+            Trigger::Mouse(button) => self.button_released(button),
+            Trigger::WheelUp | Trigger::WheelDown => false,
+        }
+    }
+}
+
+fn set_held<T>(
+    held: &mut HashSet<T>,
+    pressed: &mut HashSet<T>,
+    released: &mut HashSet<T>,
+    value: T,
+    state: ElementState,
+) where
+    T: Copy + Eq + std::hash::Hash,
+{
+    match state {
+        ElementState::Pressed => {
+            if held.insert(value) {
+                pressed.insert(value);
+            }
+        }
+        ElementState::Released => {
+            if held.remove(&value) {
+                released.insert(value);
+            }
+        }
+    }
+}
+
+/// Named actions and axes, grouped in contexts that can be switched off.
+#[derive(Debug)]
+pub struct ActionMap {
+    bindings: Bindings,
+    disabled: BTreeSet<String>,
+}
+
+impl ActionMap {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            disabled: BTreeSet::new(),
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    /// Enables or disables every binding in `context`, the global context can't be disabled.
+    pub fn set_context(&mut self, context: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(context);
+        } else if context != GLOBAL {
+            self.disabled.insert(context.to_owned());
+        }
+    }
+
+    pub fn context_enabled(&self, context: &str) -> bool {
+        !self.disabled.contains(context)
+    }
+
+    /// Resolves the actions and axes against `input`.
+    pub fn state<'a>(&'a self, input: &'a InputState) -> Actions<'a> {
+        Actions { map: self, input }
+    }
+
+    fn contexts(&self) -> impl Iterator<Item = &ContextBindings> {
+        let disabled = &self.disabled;
+        self.bindings
+            .contexts
+            .iter()
+            .filter(move |(name, _)| !disabled.contains(name.as_str()))
+            .map(|(_, context)| context)
+    }
+
+    fn chords<'a>(&'a self, action: &'a str) -> impl Iterator<Item = &'a Chord> {
+        self.contexts()
+            .filter_map(move |context| context.actions.get(action))
+            .flatten()
+    }
+
+    /// Checks whether `event` is a press that fires `action`, without needing
+    /// the event to go through the input thread first.
+    pub fn triggered_by(
+        &self,
+        action: &str,
+        event: &WindowEvent,
+        modifiers: ModifiersState,
+    ) -> bool {
+        let trigger = match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => Trigger::Key(key),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => Trigger::Mouse(button),
+            _ => return false,
+        };
+        self.chords(action).any(|c| c.matches(trigger, modifiers))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Actions<'a> {
+    map: &'a ActionMap,
+    input: &'a InputState,
+}
+
+impl Actions<'_> {
+    fn chord_held(&self, chord: &Chord) -> bool {
+        self.input.held(chord.trigger) && self.input.modifiers.contains(chord.modifiers)
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.map.chords(action).any(|c| self.chord_held(c))
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.map
+            .chords(action)
+            .any(|c| self.input.pressed(c.trigger) && self.input.modifiers.contains(c.modifiers))
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.map
+            .chords(action)
+            .any(|c| self.input.released(c.trigger))
+    }
+
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.map
+            .contexts()
+            .filter_map(|context| context.axes.get(axis))
+            .flatten()
+            .map(|binding| match *binding {
+                AxisBinding::Keys {
+                    negative,
+                    positive,
+                    scale,
+                } => {
+                    let value =
+                        self.chord_held(&positive) as i32 - self.chord_held(&negative) as i32;
+                    value as f32 * scale
+                }
+                AxisBinding::Wheel { scale } => self.input.scroll.y * scale,
+                AxisBinding::CursorX { scale } => self.input.cursor_delta_ndc().x * scale,
+                AxisBinding::CursorY { scale } => self.input.cursor_delta_ndc().y * scale,
+                AxisBinding::MouseX { scale } => self.input.mouse_delta.x * scale,
+                AxisBinding::MouseY { scale } => self.input.mouse_delta.y * scale,
+            })
+            .sum()
+    }
+}
 
 pub fn spawn_input_thread<T>(
-    queue: Arc<(Mutex<VecDeque<RunnerEvent>>, Condvar)>,
+    queue: EventReceiver,
     runner: Arc<Mutex<T>>,
-) -> JoinHandle<()>
+    input: Arc<Mutex<InputState>>,
+    mut recorder: Option<Recorder>,
+    shutdown: Shutdown,
+) -> EngineThread
 where
     T: ThreadRunner + Send + Sync + 'static,
 {
-    std::thread::spawn(move || {
-        let (lock, cvar) = &*queue;
-        let mut queue = lock.lock();
-        loop {
-            let len = queue.len();
-            if len > 20 {
-                log::warn!("input queue backed up: {}", len)
-            }
-            while let Some(event) = queue.pop_front() {
+    EngineThread::spawn("input", move || {
+        while !shutdown.requested() {
+            let event = match queue.recv_timeout(SHUTDOWN_POLL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            {
+                let mut input = input.lock();
+                if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(input.tick, &event)) {
+                    log::warn!("stopped recording input: {}", e);
+                    recorder = None;
+                }
+                input.handle_event(&event);
+            }
+            {
                 let mut runner = runner.lock();
+                let _input = timing::scope(Phase::Input);
                 runner.input(event);
             }
-            cvar.wait(&mut queue);
+            queue.handled();
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use winit::event::DeviceId;
+
+    use super::*;
+
+    const GAME: &str = "game";
+    const JUMP: &str = "jump";
+    const FIRE: &str = "fire";
+    const SAVE: &str = "save";
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    fn input(events: Vec<WindowEvent>) -> InputState {
+        let mut input = InputState::new(Size::new(800, 600));
+        for event in events {
+            input.handle_event(&RunnerEvent::Window(WindowId::MAIN, event));
+        }
+        input
+    }
+
+    fn press(keys: &[VirtualKeyCode]) -> InputState {
+        input(
+            keys.iter()
+                .map(|&k| key(k, ElementState::Pressed))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut bindings = Bindings::default();
+        bindings.action(GAME, JUMP, Chord::key(VirtualKeyCode::Space));
+        let mut map = ActionMap::new(bindings);
+        let space = press(&[VirtualKeyCode::Space]);
+        let enter = press(&[VirtualKeyCode::Return]);
+        assert!(map.state(&space).pressed(JUMP));
+        assert!(map.state(&space).just_pressed(JUMP));
+
+        let mut bindings = Bindings::default();
+        bindings.action(GAME, JUMP, Chord::key(VirtualKeyCode::Return));
+        map.set_bindings(bindings);
+        assert!(!map.state(&space).pressed(JUMP));
+        assert!(map.state(&enter).pressed(JUMP));
+    }
+
+    #[test]
+    fn merge_keeps_rebound_actions() {
+        let mut user = Bindings::default();
+        user.action(GAME, JUMP, Chord::key(VirtualKeyCode::Return));
+        let mut defaults = Bindings::default();
+        defaults
+            .action(GAME, JUMP, Chord::key(VirtualKeyCode::Space))
+            .action(GAME, FIRE, Chord::mouse(MouseButton::Left));
+        user.merge(defaults);
+
+        let actions = &user.contexts[GAME].actions;
+        assert_eq!(actions[JUMP], vec![Chord::key(VirtualKeyCode::Return)]);
+        assert_eq!(actions[FIRE], vec![Chord::mouse(MouseButton::Left)]);
+    }
+
+    #[test]
+    fn conflicting_triggers() {
+        let mut bindings = Bindings::default();
+        bindings
+            .action(GAME, JUMP, Chord::key(VirtualKeyCode::S))
+            .action(GAME, FIRE, Chord::key(VirtualKeyCode::S))
+            .action(
+                GAME,
+                SAVE,
+                Chord::key(VirtualKeyCode::S).with(ModifiersState::CTRL),
+            );
+        let map = ActionMap::new(bindings);
+
+        // every action on a chord fires, extra modifiers don't get in the way
+        let plain = press(&[VirtualKeyCode::S]);
+        let mut ctrl = plain.clone();
+        ctrl.modifiers = ModifiersState::CTRL;
+        for &action in [JUMP, FIRE].iter() {
+            assert!(map.state(&plain).pressed(action));
+            assert!(map.state(&ctrl).pressed(action));
+        }
+        assert!(!map.state(&plain).pressed(SAVE));
+        assert!(map.state(&ctrl).pressed(SAVE));
+
+        let event = key(VirtualKeyCode::S, ElementState::Pressed);
+        assert!(map.triggered_by(FIRE, &event, ModifiersState::empty()));
+        assert!(!map.triggered_by(SAVE, &event, ModifiersState::empty()));
+        assert!(map.triggered_by(SAVE, &event, ModifiersState::CTRL));
+    }
+
+    #[test]
+    fn opposing_axis_keys_cancel() {
+        let mut bindings = Bindings::default();
+        bindings.axis(
+            GAME,
+            "move",
+            AxisBinding::Keys {
+                negative: Chord::key(VirtualKeyCode::A),
+                positive: Chord::key(VirtualKeyCode::D),
+                scale: 2.0,
+            },
+        );
+        let map = ActionMap::new(bindings);
+        assert_eq!(map.state(&press(&[VirtualKeyCode::D])).axis("move"), 2.0);
+        assert_eq!(map.state(&press(&[VirtualKeyCode::A])).axis("move"), -2.0);
+        let both = press(&[VirtualKeyCode::A, VirtualKeyCode::D]);
+        assert_eq!(map.state(&both).axis("move"), 0.0);
+    }
+
+    #[test]
+    fn disabled_contexts() {
+        let mut bindings = Bindings::engine();
+        bindings.action(GAME, JUMP, Chord::key(VirtualKeyCode::Space));
+        let mut map = ActionMap::new(bindings);
+        let input = press(&[VirtualKeyCode::Space, VirtualKeyCode::Escape]);
+
+        map.set_context(GAME, false);
+        map.set_context(GLOBAL, false);
+        assert!(!map.context_enabled(GAME));
+        assert!(!map.state(&input).pressed(JUMP));
+        assert!(map.state(&input).pressed(QUIT));
+
+        map.set_context(GAME, true);
+        assert!(map.state(&input).pressed(JUMP));
+    }
+
+    fn cursor(x: f64, y: f64) -> WindowEvent {
+        WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: (x, y),
+        }
+    }
+
+    #[test]
+    fn cursor_ndc() {
+        let input = input(vec![cursor(0.0, 600.0), cursor(400.0, 300.0)]);
+        assert_eq!(input.cursor_ndc(), Some(glam::Vec2::ZERO));
+        assert_eq!(input.cursor_delta_ndc(), glam::vec2(1.0, -1.0));
+    }
+
+    #[test]
+    fn zero_sized_window() {
+        for &size in [Size::new(0, 0), Size::new(800, 0), Size::new(0, 600)].iter() {
+            let input = input(vec![
+                WindowEvent::Resized(size),
+                cursor(0.0, 0.0),
+                cursor(10.0, 10.0),
+            ]);
+            assert_eq!(input.cursor_ndc(), None);
+            assert_eq!(input.cursor_delta_ndc(), glam::Vec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut bindings = Bindings::engine();
+        bindings
+            .action(
+                GAME,
+                SAVE,
+                Chord::key(VirtualKeyCode::S).with(ModifiersState::CTRL),
+            )
+            .axis(GAME, "zoom", AxisBinding::Wheel { scale: -0.5 });
+        let json = serde_json::to_string(&bindings).unwrap();
+        let read: Bindings = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&read).unwrap(),
+            serde_json::to_value(&bindings).unwrap()
+        );
+    }
+
+    #[test]
+    fn serde_defaults() {
+        let json = r#"{ "contexts": { "game": {
+            "actions": { "jump": [{ "trigger": { "Key": "Space" } }] },
+            "axes": { "zoom": [{ "Wheel": {} }] }
+        } } }"#;
+        let bindings: Bindings = serde_json::from_str(json).unwrap();
+        let context = &bindings.contexts[GAME];
+        assert_eq!(
+            context.actions[JUMP],
+            vec![Chord::key(VirtualKeyCode::Space)]
+        );
+        assert_eq!(
+            context.axes["zoom"],
+            vec![AxisBinding::Wheel { scale: 1.0 }]
+        );
+    }
+}
@@ -1,13 +1,22 @@
 #![feature(duration_consts_2)]
 #![feature(duration_saturating_ops)]
 
-use std::{collections::VecDeque, sync::Arc, time::Duration};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
+use error::StartupError;
 use futures::executor::block_on;
-use inputs::spawn_input_thread;
-use parking_lot::{Condvar, Mutex};
-use render::RenderTarget;
+use inputs::{spawn_input_thread, ActionMap, Bindings, InputState};
+use lifecycle::{EngineThreads, Shutdown};
+use options::Options;
+use parking_lot::Mutex;
+use queue::{EventSender, QueueConfig};
+use render::{RenderError, RenderTarget, SimulatedFaults};
+use replay::{Recorder, Replay};
 use updates::spawn_update_thread;
+use windows::WindowId;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -16,12 +25,19 @@ use winit::{
 
 pub mod camera;
 pub mod clock;
+pub mod error;
 pub mod event;
 pub mod graphics;
 pub mod inputs;
+pub mod lifecycle;
+pub mod options;
+pub mod queue;
 pub mod render;
+pub mod replay;
 pub mod resources;
+pub mod timing;
 pub mod updates;
+pub mod windows;
 
 pub use crate::{
     graphics::common::Size,
@@ -46,6 +62,14 @@ pub trait MainRunner {
         sc_desc: &wgpu::SwapChainDescriptor,
         runner: Arc<Mutex<Self::Runner>>,
     ) -> Self;
+    /// Bindings used when the bindings file doesn't define them.
+    fn default_bindings() -> Bindings {
+        Bindings::default()
+    }
+    /// Capacity and backpressure of the queue feeding the input thread.
+    fn queue_config() -> QueueConfig {
+        QueueConfig::default()
+    }
     fn global_event(
         &mut self,
         event: &Event<RunnerEvent>,
@@ -69,6 +93,33 @@ pub trait MainRunner {
         encoder: &mut wgpu::CommandEncoder,
         window: &winit::window::Window,
     );
+    /// Draws a window opened through [`windows::open`].
+    fn render_window(
+        &mut self,
+        _id: WindowId,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _target: &RenderTarget,
+        _frame: &wgpu::TextureView,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+    }
+    /// Called after the device was lost and recreated, every GPU resource made
+    /// with the old device has to be rebuilt.
+    fn device_recreated(
+        &mut self,
+        _window: &winit::window::Window,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _sc_desc: &wgpu::SwapChainDescriptor,
+    ) {
+    }
+    /// Called when the window is asked to close, return `false` to keep it open.
+    fn close_requested(&mut self, _window: &winit::window::Window) -> bool {
+        true
+    }
+    /// Called last, after the engine threads and the thread runner have stopped.
+    fn shutdown(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {}
 }
 
 pub trait ThreadRunner {
@@ -92,6 +143,8 @@ pub trait ThreadRunner {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         delta: (f32, Duration),
+        input: &InputState,
+        actions: &mut ActionMap,
     ) -> u32;
     fn render(
         &mut self,
@@ -102,6 +155,29 @@ pub trait ThreadRunner {
         encoder: &mut wgpu::CommandEncoder,
         window: &winit::window::Window,
     );
+    /// Draws a window opened through [`windows::open`].
+    fn render_window(
+        &mut self,
+        _id: WindowId,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _target: &RenderTarget,
+        _frame: &wgpu::TextureView,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+    }
+    /// Called after the device was lost and recreated, every GPU resource made
+    /// with the old device has to be rebuilt.
+    fn device_recreated(
+        &mut self,
+        _window: &winit::window::Window,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _sc_desc: &wgpu::SwapChainDescriptor,
+    ) {
+    }
+    /// Called on the main thread once the input and update threads have stopped.
+    fn shutdown(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {}
 }
 
 pub fn run<T>() -> Result<(), Box<dyn std::error::Error>>
@@ -109,29 +185,33 @@ where
     T: MainRunner + 'static,
 {
     env_logger::init();
+    let options = Options::from_args();
+    if options.list_adapters {
+        render::list_adapters();
+        return Ok(());
+    }
     let event_loop = EventLoop::with_user_event();
     let event_proxy = event_loop.create_proxy();
     let window = WindowBuilder::new()
         .with_inner_size(winit::dpi::LogicalSize::new(1280, 720))
         .build(&event_loop)
-        .unwrap();
+        .map_err(StartupError::from)?;
 
     let mut frame_time = std::time::Duration::from_secs_f32(1.0 / 60.0);
     let mut fps = 60.0;
     let mut clock = clock::Clock::new(60);
 
-    let renderer = Arc::new(block_on(render::RenderState::new(&window)));
+    let renderer = Arc::new(block_on(render::RenderState::new(&window))?);
+    if !options.faults.is_empty() {
+        renderer.simulate_faults(SimulatedFaults::new(options.faults.iter().copied()));
+    }
+    let gpu = renderer.gpu();
 
-    crate::resources::load(&renderer.device, &renderer.queue)?;
+    crate::resources::load(&gpu.device, &gpu.queue)?;
 
     let thread_runner = {
         let target = renderer.target.lock();
-        <T::Runner as ThreadRunner>::build(
-            &window,
-            &renderer.device,
-            &renderer.queue,
-            &target.sc_desc(),
-        )
+        <T::Runner as ThreadRunner>::build(&window, &gpu.device, &gpu.queue, &target.sc_desc())
     };
     let thread_runner = Arc::new(Mutex::new(thread_runner));
 
@@ -140,70 +220,142 @@ where
         let thread_runner = Arc::clone(&thread_runner);
         T::build(
             &window,
-            &renderer.device,
-            &renderer.queue,
+            &gpu.device,
+            &gpu.queue,
             &target.sc_desc(),
             thread_runner,
         )
     };
+    // the device may be replaced later, only the renderer keeps it
+    drop(gpu);
+
+    let bindings = {
+        let mut defaults = Bindings::engine();
+        defaults.merge(T::default_bindings());
+        Bindings::load_or_default(inputs::BINDINGS_FILE, defaults)
+    };
+    let actions = Arc::new(Mutex::new(ActionMap::new(bindings)));
+    let input = Arc::new(Mutex::new(InputState::new(window.inner_size().into())));
 
     let window = Arc::new(window);
-    let queue = Arc::new((Mutex::new(VecDeque::new()), Condvar::new()));
+    let (mut events, queue) = queue::event_queue(T::queue_config());
 
-    spawn_input_thread(Arc::clone(&queue), Arc::clone(&thread_runner));
-    spawn_update_thread(
-        Arc::clone(&thread_runner),
-        Arc::clone(&renderer),
-        Arc::clone(&window),
-    );
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
+    let replay = match &options.replay {
+        Some(path) => Some(Replay::load(path, events.clone())?),
+        None => None,
+    };
+    let replaying = replay.as_ref().map(Replay::active).unwrap_or_default();
+
+    let shutdown = Shutdown::new();
+    let threads = vec![
+        spawn_input_thread(
+            queue,
+            Arc::clone(&thread_runner),
+            Arc::clone(&input),
+            recorder,
+            shutdown.clone(),
+        ),
+        spawn_update_thread(
+            Arc::clone(&thread_runner),
+            Arc::clone(&renderer),
+            Arc::clone(&window),
+            Arc::clone(&input),
+            Arc::clone(&actions),
+            replay,
+            options.fixed_timestep(),
+            shutdown.clone(),
+        ),
+    ];
+    let mut threads = Some(EngineThreads { shutdown, threads });
 
-    event_loop.run(move |event, _, control_flow| {
+    // while a replay is running the window's own input is ignored
+    let push_event = move |events: &mut EventSender, event: RunnerEvent| {
+        if !replaying.load(Ordering::Acquire) {
+            events.push(event);
+        }
+    };
+
+    event_loop.run(move |event, event_loop, control_flow| {
         runner.global_event(&event, &window, control_flow);
         match event {
             Event::DeviceEvent { event, .. } => {
-                let (lock, cvar) = &*queue;
-                lock.lock().push_back(RunnerEvent::Device(event));
-                cvar.notify_one();
+                push_event(&mut events, RunnerEvent::Device(event.into()))
             }
             Event::WindowEvent { event, window_id } if window_id == window.id() => {
                 let event: event::WindowEvent = event.into();
                 match event {
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::CloseRequested => {
+                        if runner.close_requested(&window) {
+                            *control_flow = ControlFlow::Exit
+                        }
+                    }
                     WindowEvent::Resized(size) => {
                         let runner = Arc::clone(&thread_runner);
                         let renderer = Arc::clone(&renderer);
                         renderer.resize(size, runner);
+                        push_event(&mut events, RunnerEvent::Window(WindowId::MAIN, event));
                     }
                     WindowEvent::ScaleFactorChanged { size, .. } => {
                         let runner = Arc::clone(&thread_runner);
                         let renderer = Arc::clone(&renderer);
                         renderer.resize(size, runner);
+                        push_event(&mut events, RunnerEvent::Window(WindowId::MAIN, event));
                     }
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    event => {
-                        let (lock, cvar) = &*queue;
-                        lock.lock().push_back(RunnerEvent::Window(event));
-                        cvar.notify_one();
+                    event
+                        if actions.lock().triggered_by(
+                            inputs::QUIT,
+                            &event,
+                            input.lock().modifiers,
+                        ) =>
+                    {
+                        if runner.close_requested(&window) {
+                            *control_flow = ControlFlow::Exit
+                        }
                     }
+                    event => push_event(&mut events, RunnerEvent::Window(WindowId::MAIN, event)),
                 }
             }
-            Event::UserEvent(event) => {
-                let (lock, cvar) = &*queue;
-                lock.lock().push_back(event);
-                cvar.notify_one();
+            Event::WindowEvent { event, window_id } => {
+                if let Some(id) = renderer.window_id(window_id) {
+                    let event: event::WindowEvent = event.into();
+                    match event {
+                        WindowEvent::Resized(size)
+                        | WindowEvent::ScaleFactorChanged { size, .. } => {
+                            renderer.resize_window(id, size)
+                        }
+                        WindowEvent::CloseRequested => {
+                            renderer.remove_window(id);
+                        }
+                        _ => {}
+                    }
+                    push_event(&mut events, RunnerEvent::Window(id, event));
+                }
+            }
+            Event::UserEvent(event) => push_event(&mut events, event),
+            Event::RedrawRequested(window_id) if window_id != window.id() => {
+                if let Some(id) = renderer.window_id(window_id) {
+                    let result =
+                        renderer.render_window(id, Arc::clone(&thread_runner), &mut runner);
+                    match result {
+                        Ok(_) | Err(RenderError::Timeout) => {}
+                        Err(RenderError::Outdated) | Err(RenderError::SurfaceLost) => {
+                            renderer.rebuild_window(id)
+                        }
+                        Err(e) => log::warn!("{}", e),
+                    }
+                }
             }
             Event::RedrawRequested(_) => {
                 clock.tick();
+                timing::timings().lock().end_frame();
 
-                clock.target_rate = runner.update(&window, &renderer.device, &renderer.queue);
+                let gpu = renderer.gpu();
+                clock.target_rate = runner.update(&window, &gpu.device, &gpu.queue);
+                drop(gpu);
                 match renderer.render(&window, Arc::clone(&thread_runner), &mut runner) {
                     Ok(_) => {
                         event_proxy
@@ -216,19 +368,55 @@ where
                         frame_time = curr_time;
                         fps = tick_rate;
                     }
-                    Err(wgpu::SwapChainError::Lost) => {
-                        let size = {
-                            let target = renderer.target.lock();
-                            target.size()
-                        };
-                        renderer.resize(size, Arc::clone(&thread_runner));
+                    Err(error) => {
+                        let recovered = renderer.recover(
+                            &window,
+                            error,
+                            Arc::clone(&thread_runner),
+                            &mut runner,
+                        );
+                        if let Err(e) = recovered {
+                            log::error!("failed to recover from '{}': {}", error, e);
+                            *control_flow = ControlFlow::Exit;
+                        }
                     }
-                    Err(wgpu::SwapChainError::OutOfMemory) => {}
-                    Err(e) => log::warn!("{}", e),
                 }
             }
             Event::MainEventsCleared => {
+                events.flush();
+                let (open, close) = windows::window_requests().lock().take();
+                for id in close {
+                    renderer.remove_window(id);
+                }
+                for (id, request) in open {
+                    let built = WindowBuilder::new()
+                        .with_title(request.title)
+                        .with_inner_size(winit::dpi::PhysicalSize::new(
+                            request.size.width,
+                            request.size.height,
+                        ))
+                        .build(event_loop);
+                    match built {
+                        Ok(built) => renderer.add_window(id, built),
+                        Err(e) => log::warn!("failed to open window: {}", e),
+                    }
+                }
                 window.request_redraw();
+                renderer.request_redraws();
+            }
+            Event::LoopDestroyed => {
+                if let Some(threads) = threads.take() {
+                    log::info!("shutting down");
+                    threads.stop(lifecycle::SHUTDOWN_TIMEOUT);
+                }
+                let gpu = renderer.gpu();
+                // a thread that didn't stop may still hold the runner
+                match thread_runner.try_lock_for(lifecycle::SHUTDOWN_TIMEOUT) {
+                    Some(mut thread_runner) => thread_runner.shutdown(&gpu.device, &gpu.queue),
+                    None => log::warn!("thread runner is busy, skipping its shutdown"),
+                }
+                runner.shutdown(&gpu.device, &gpu.queue);
+                gpu.device.poll(wgpu::Maintain::Wait);
             }
             _ => {}
         }
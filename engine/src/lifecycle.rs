@@ -0,0 +1,98 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How long `run` waits for the engine threads before giving up on them.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Stop signal shared by the engine threads.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn requested(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// A named thread that can be joined with a timeout.
+pub struct EngineThread {
+    name: String,
+    handle: JoinHandle<()>,
+    done: mpsc::Receiver<()>,
+}
+
+impl EngineThread {
+    pub fn spawn<F>(name: &str, f: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // dropped when the thread returns or unwinds, which wakes up `join`
+        let (done_tx, done) = mpsc::channel::<()>();
+        let handle = std::thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || {
+                let _done = done_tx;
+                f();
+            })
+            .expect("failed to spawn engine thread");
+        Self {
+            name: name.to_owned(),
+            handle,
+            done,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `false` if the thread didn't finish by `deadline`, it is left
+    /// running in that case.
+    pub fn join(self, deadline: Instant) -> bool {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.done.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!("{} thread didn't stop in time", self.name);
+                false
+            }
+            _ => {
+                if self.handle.join().is_err() {
+                    log::error!("{} thread panicked", self.name);
+                }
+                true
+            }
+        }
+    }
+}
+
+/// The input and update threads started by `run`.
+pub struct EngineThreads {
+    pub shutdown: Shutdown,
+    pub threads: Vec<EngineThread>,
+}
+
+impl EngineThreads {
+    /// Signals every thread to stop and waits up to `timeout` for all of them.
+    pub fn stop(self, timeout: Duration) -> bool {
+        self.shutdown.request();
+        let deadline = Instant::now() + timeout;
+        self.threads
+            .into_iter()
+            .fold(true, |stopped, thread| thread.join(deadline) && stopped)
+    }
+}
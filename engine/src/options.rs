@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use crate::render::RenderError;
+
+/// Command line options understood by the engine.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Write every input event to this file.
+    pub record: Option<PathBuf>,
+    /// Feed the events from this recording instead of the window's.
+    pub replay: Option<PathBuf>,
+    /// Run updates with a constant delta instead of the measured one.
+    pub fixed_timestep: bool,
+    /// Print the available graphics adapters and exit.
+    pub list_adapters: bool,
+    /// Render errors to raise on given frames, from `--simulate-fault <error>@<frame>`.
+    pub faults: Vec<(u64, RenderError)>,
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        let mut options = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record" => options.record = args.next().map(PathBuf::from),
+                "--replay" => options.replay = args.next().map(PathBuf::from),
+                "--fixed-timestep" => options.fixed_timestep = true,
+                "--list-adapters" => options.list_adapters = true,
+                "--simulate-fault" => match args.next().as_deref().map(parse_fault) {
+                    Some(Ok(fault)) => options.faults.push(fault),
+                    Some(Err(e)) => log::warn!("{}", e),
+                    None => log::warn!("--simulate-fault needs <error>@<frame>"),
+                },
+                arg => log::warn!("unknown argument '{}'", arg),
+            }
+        }
+        options
+    }
+
+    /// Recording and replaying only line up when every tick has the same delta.
+    pub fn fixed_timestep(&self) -> bool {
+        self.fixed_timestep || self.record.is_some() || self.replay.is_some()
+    }
+}
+
+fn parse_fault(arg: &str) -> Result<(u64, RenderError), String> {
+    let (error, frame) = arg
+        .split_once('@')
+        .ok_or_else(|| format!("expected <error>@<frame>, got '{}'", arg))?;
+    let frame = frame
+        .parse()
+        .map_err(|_| format!("invalid frame '{}'", frame))?;
+    Ok((frame, error.parse()?))
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+
+use crate::{
+    event::{DeviceEvent, RunnerEvent, WindowEvent},
+    windows::WindowId,
+};
+
+lazy_static! {
+    static ref QUEUE_STATS: Arc<QueueStats> = Arc::new(QueueStats::default());
+}
+
+pub fn queue_stats() -> Arc<QueueStats> {
+    Arc::clone(&QUEUE_STATS)
+}
+
+/// What to do with an event when the input queue is full.
+///
+/// Only motion events are ever dropped, anything else waits for room so no key
+/// or button edge gets lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the input thread to make room. The event loop pushes into the
+    /// queue, so a stalled input thread freezes the window.
+    Block,
+    /// Drop a motion event that doesn't fit.
+    DropNewest,
+    /// Drop the oldest queued motion event to make room, the latest input wins.
+    DropOldest,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub backpressure: Backpressure,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            backpressure: Backpressure::DropOldest,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    /// Notified when `depth` drops to zero.
+    drained: Condvar,
+    drained_lock: Mutex<()>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueReport {
+    /// Events sent but not yet handled by the input thread.
+    pub depth: usize,
+    pub max_depth: usize,
+    pub sent: u64,
+    pub dropped: u64,
+    /// Motion events folded into an earlier one.
+    pub coalesced: u64,
+}
+
+impl QueueStats {
+    pub fn report(&self) -> QueueReport {
+        QueueReport {
+            depth: self.depth.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Acquire)
+    }
+
+    /// Waits up to `timeout` for every sent event to be handled, returns
+    /// whether the queue is drained.
+    pub fn wait_drained(&self, timeout: Duration) -> bool {
+        let mut lock = self.drained_lock.lock();
+        if self.depth() == 0 {
+            return true;
+        }
+        self.drained.wait_for(&mut lock, timeout);
+        self.depth() == 0
+    }
+
+    fn done(&self) {
+        if self.depth.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // taking the lock orders this after a waiter's depth check
+            let _lock = self.drained_lock.lock();
+            self.drained.notify_all();
+        }
+    }
+
+    /// Counts an event before it is sent, so the input thread can never see it
+    /// handled before it was counted.
+    fn sending(&self) {
+        let depth = self.depth.fetch_add(1, Ordering::AcqRel) + 1;
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    fn sent(&self, sent: bool) {
+        if sent {
+            self.sent.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.done();
+        }
+    }
+}
+
+/// Bounded queue shared by the senders and the receiver.
+struct Channel {
+    events: Mutex<VecDeque<RunnerEvent>>,
+    capacity: usize,
+    /// Notified when an event is queued or the last sender is gone.
+    available: Condvar,
+    /// Notified when an event is taken or the receiver is gone.
+    room: Condvar,
+    senders: AtomicUsize,
+    receiver: AtomicBool,
+}
+
+impl Channel {
+    fn disconnect(&self, condvar: &Condvar) {
+        let _events = self.events.lock();
+        condvar.notify_all();
+    }
+}
+
+pub fn event_queue(config: QueueConfig) -> (EventSender, EventReceiver) {
+    let channel = Arc::new(Channel {
+        events: Mutex::new(VecDeque::with_capacity(config.capacity)),
+        capacity: config.capacity.max(1),
+        available: Condvar::new(),
+        room: Condvar::new(),
+        senders: AtomicUsize::new(1),
+        receiver: AtomicBool::new(true),
+    });
+    let stats = queue_stats();
+    (
+        EventSender {
+            channel: Arc::clone(&channel),
+            stats: Arc::clone(&stats),
+            backpressure: config.backpressure,
+            cursor: None,
+            motion: None,
+        },
+        EventReceiver { channel, stats },
+    )
+}
+
+/// Sending half of the input queue.
+///
+/// Runs of `CursorMoved` and `MouseMotion` events are held back and sent as a
+/// single event once something else arrives, the cursor moves to another window
+/// or [`EventSender::flush`] is called.
+pub struct EventSender {
+    channel: Arc<Channel>,
+    stats: Arc<QueueStats>,
+    backpressure: Backpressure,
+    cursor: Option<(WindowId, WindowEvent)>,
+    motion: Option<(f64, f64)>,
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            channel: Arc::clone(&self.channel),
+            stats: Arc::clone(&self.stats),
+            backpressure: self.backpressure,
+            cursor: None,
+            motion: None,
+        }
+    }
+}
+
+impl EventSender {
+    pub fn push(&mut self, event: RunnerEvent) {
+        match event {
+            RunnerEvent::Window(window, event @ WindowEvent::CursorMoved { .. }) => {
+                match &self.cursor {
+                    Some((last, _)) if *last == window => {
+                        self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Some(_) => self.flush(),
+                    None => {}
+                }
+                self.cursor = Some((window, event));
+            }
+            RunnerEvent::Device(DeviceEvent::MouseMotion { delta }) => {
+                if let Some(motion) = self.motion.as_mut() {
+                    motion.0 += delta.0;
+                    motion.1 += delta.1;
+                    self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.motion = Some(delta);
+                }
+            }
+            event => {
+                self.flush();
+                self.send(event);
+            }
+        }
+    }
+
+    /// Sends the motion held back by [`EventSender::push`].
+    pub fn flush(&mut self) {
+        if let Some((window, event)) = self.cursor.take() {
+            self.send(RunnerEvent::Window(window, event));
+        }
+        if let Some(delta) = self.motion.take() {
+            self.send(RunnerEvent::Device(DeviceEvent::MouseMotion { delta }));
+        }
+    }
+
+    /// Sends `event` right away, without coalescing, applying the configured backpressure.
+    pub fn send(&self, event: RunnerEvent) {
+        self.send_with(event, self.backpressure);
+    }
+
+    /// Sends `event`, waiting for room regardless of the configured backpressure.
+    pub fn send_blocking(&self, event: RunnerEvent) {
+        self.send_with(event, Backpressure::Block);
+    }
+
+    fn send_with(&self, event: RunnerEvent, backpressure: Backpressure) {
+        self.stats.sending();
+        let channel = &self.channel;
+        let mut events = channel.events.lock();
+        let sent = loop {
+            if !channel.receiver.load(Ordering::Acquire) {
+                break false;
+            }
+            if events.len() < channel.capacity {
+                events.push_back(event);
+                channel.available.notify_one();
+                break true;
+            }
+            let oldest_motion = match backpressure {
+                Backpressure::DropOldest => events.iter().position(is_motion),
+                _ => None,
+            };
+            if let Some(index) = oldest_motion {
+                events.remove(index);
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                self.stats.done();
+            } else if backpressure != Backpressure::Block && is_motion(&event) {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                break false;
+            } else {
+                channel.room.wait(&mut events);
+            }
+        };
+        drop(events);
+        self.stats.sent(sent);
+    }
+
+    pub fn stats(&self) -> &QueueStats {
+        &self.stats
+    }
+}
+
+/// Events that can be dropped under backpressure, losing one only costs some
+/// cursor movement.
+fn is_motion(event: &RunnerEvent) -> bool {
+    matches!(
+        event,
+        RunnerEvent::Window(_, WindowEvent::CursorMoved { .. })
+            | RunnerEvent::Device(DeviceEvent::MouseMotion { .. })
+    )
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.disconnect(&self.channel.available);
+        }
+    }
+}
+
+pub struct EventReceiver {
+    channel: Arc<Channel>,
+    stats: Arc<QueueStats>,
+}
+
+impl EventReceiver {
+    /// Blocks until an event arrives, `None` once every sender is gone.
+    pub fn recv(&self) -> Option<RunnerEvent> {
+        let channel = &self.channel;
+        let mut events = channel.events.lock();
+        loop {
+            if let Some(event) = events.pop_front() {
+                channel.room.notify_one();
+                return Some(event);
+            }
+            if channel.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            channel.available.wait(&mut events);
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<RunnerEvent, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let channel = &self.channel;
+        let mut events = channel.events.lock();
+        loop {
+            if let Some(event) = events.pop_front() {
+                channel.room.notify_one();
+                return Ok(event);
+            }
+            if channel.senders.load(Ordering::Acquire) == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            if channel
+                .available
+                .wait_until(&mut events, deadline)
+                .timed_out()
+            {
+                return Err(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+
+    /// Marks an event returned by [`EventReceiver::recv`] as fully handled.
+    pub fn handled(&self) {
+        self.stats.done();
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        self.channel.receiver.store(false, Ordering::Release);
+        self.channel.disconnect(&self.channel.room);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{DeviceId, ElementState, KeyboardInput, ModifiersState};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Sent {
+        Key(u32),
+        Cursor(u32),
+    }
+
+    #[allow(deprecated)]
+    fn key(id: u32) -> RunnerEvent {
+        RunnerEvent::Window(
+            WindowId::MAIN,
+            WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: id,
+                    state: ElementState::Pressed,
+                    virtual_keycode: None,
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: false,
+            },
+        )
+    }
+
+    fn cursor(id: u32) -> RunnerEvent {
+        RunnerEvent::Window(
+            WindowId::MAIN,
+            WindowEvent::CursorMoved {
+                device_id: unsafe { DeviceId::dummy() },
+                position: (id as f64, 0.0),
+            },
+        )
+    }
+
+    fn recv(receiver: &EventReceiver) -> Option<Sent> {
+        let event = receiver.recv()?;
+        receiver.handled();
+        Some(match event {
+            RunnerEvent::Window(_, WindowEvent::KeyboardInput { input, .. }) => {
+                Sent::Key(input.scancode)
+            }
+            RunnerEvent::Window(_, WindowEvent::CursorMoved { position, .. }) => {
+                Sent::Cursor(position.0 as u32)
+            }
+            event => panic!("unexpected event {:?}", event),
+        })
+    }
+
+    fn drain(receiver: &EventReceiver) -> Vec<Sent> {
+        std::iter::from_fn(|| recv(receiver)).collect()
+    }
+
+    fn queue(capacity: usize, backpressure: Backpressure) -> (EventSender, EventReceiver) {
+        event_queue(QueueConfig {
+            capacity,
+            backpressure,
+        })
+    }
+
+    #[test]
+    fn drop_oldest_evicts_motion() {
+        let (sender, receiver) = queue(3, Backpressure::DropOldest);
+        sender.send(cursor(0));
+        sender.send(key(0));
+        sender.send(cursor(1));
+        sender.send(key(1));
+        sender.send(cursor(2));
+        drop(sender);
+        assert_eq!(
+            drain(&receiver),
+            vec![Sent::Key(0), Sent::Key(1), Sent::Cursor(2)]
+        );
+    }
+
+    #[test]
+    fn drop_newest_drops_motion() {
+        let (sender, receiver) = queue(2, Backpressure::DropNewest);
+        sender.send(key(0));
+        sender.send(key(1));
+        sender.send(cursor(0));
+        drop(sender);
+        assert_eq!(drain(&receiver), vec![Sent::Key(0), Sent::Key(1)]);
+    }
+
+    #[test]
+    fn full_queue_keeps_key_edges() {
+        for &backpressure in [Backpressure::DropNewest, Backpressure::DropOldest].iter() {
+            let (sender, receiver) = queue(4, backpressure);
+            let producer = std::thread::spawn(move || {
+                for id in 0..64 {
+                    sender.send(cursor(id));
+                    sender.send(key(id));
+                }
+            });
+            // let the producer run into a full queue before draining it
+            std::thread::sleep(Duration::from_millis(20));
+            let keys: Vec<_> = drain(&receiver)
+                .into_iter()
+                .filter(|sent| matches!(sent, Sent::Key(_)))
+                .collect();
+            producer.join().unwrap();
+            assert_eq!(keys, (0..64).map(Sent::Key).collect::<Vec<_>>());
+        }
+    }
+}
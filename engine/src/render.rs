@@ -1,20 +1,138 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc};
 
-use parking_lot::Mutex;
+use futures::executor::block_on;
+use parking_lot::{Mutex, RwLock};
 use winit::window::Window;
 
-use crate::{MainRunner, Size, ThreadRunner};
+use crate::{
+    error::StartupError,
+    timing::{self, GpuTimer, Phase},
+    windows::WindowId,
+    MainRunner, Size, ThreadRunner,
+};
+
+/// Backends and power preferences tried in order until one yields a device.
+const ADAPTER_PREFERENCES: [(wgpu::BackendBit, wgpu::PowerPreference); 4] = [
+    (
+        wgpu::BackendBit::PRIMARY,
+        wgpu::PowerPreference::HighPerformance,
+    ),
+    (wgpu::BackendBit::PRIMARY, wgpu::PowerPreference::LowPower),
+    (
+        wgpu::BackendBit::SECONDARY,
+        wgpu::PowerPreference::HighPerformance,
+    ),
+    (wgpu::BackendBit::SECONDARY, wgpu::PowerPreference::LowPower),
+];
+
+/// Features used when the adapter has them. Without BC compression the packed
+/// textures are decompressed on the CPU.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::from_bits_truncate(
+    wgpu::Features::TEXTURE_COMPRESSION_BC.bits()
+        | wgpu::Features::TIMESTAMP_QUERY.bits()
+        | wgpu::Features::NON_FILL_POLYGON_MODE.bits(),
+);
 
 pub struct RenderTarget {
     pub sc_desc: wgpu::SwapChainDescriptor,
     pub swap_chain: wgpu::SwapChain,
 }
 
-pub struct RenderState {
+/// Everything that has to be recreated when the device is lost.
+pub struct Gpu {
+    pub instance: wgpu::Instance,
     pub surface: wgpu::Surface,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    pub timer: Option<GpuTimer>,
+}
+
+/// A window opened through [`crate::windows::open`] with its own swap chain.
+pub struct WindowTarget {
+    pub window: Window,
+    surface: wgpu::Surface,
+    pub target: RenderTarget,
+}
+
+pub struct RenderState {
+    gpu: RwLock<Arc<Gpu>>,
     pub target: Arc<Mutex<RenderTarget>>,
+    windows: Mutex<BTreeMap<WindowId, WindowTarget>>,
+    faults: Mutex<Option<SimulatedFaults>>,
+}
+
+/// Errors returned by [`RenderState::render`], see [`RenderState::recover`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderError {
+    Timeout,
+    Outdated,
+    SurfaceLost,
+    OutOfMemory,
+    /// wgpu doesn't report device loss, this only comes from [`SimulatedFaults`].
+    DeviceLost,
+}
+
+impl From<wgpu::SwapChainError> for RenderError {
+    fn from(e: wgpu::SwapChainError) -> Self {
+        match e {
+            wgpu::SwapChainError::Timeout => RenderError::Timeout,
+            wgpu::SwapChainError::Outdated => RenderError::Outdated,
+            wgpu::SwapChainError::Lost => RenderError::SurfaceLost,
+            wgpu::SwapChainError::OutOfMemory => RenderError::OutOfMemory,
+        }
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            RenderError::Timeout => "timed out waiting for a frame",
+            RenderError::Outdated => "swap chain is outdated",
+            RenderError::SurfaceLost => "surface was lost",
+            RenderError::OutOfMemory => "out of memory",
+            RenderError::DeviceLost => "device was lost",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl FromStr for RenderError {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timeout" => Ok(RenderError::Timeout),
+            "outdated" => Ok(RenderError::Outdated),
+            "surface-lost" => Ok(RenderError::SurfaceLost),
+            "oom" => Ok(RenderError::OutOfMemory),
+            "device-lost" => Ok(RenderError::DeviceLost),
+            s => Err(format!("unknown render error '{}'", s)),
+        }
+    }
+}
+
+/// Fails chosen frames with a given error instead of rendering them, so the
+/// recovery paths can be exercised on a healthy machine.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedFaults {
+    frame: u64,
+    schedule: BTreeMap<u64, RenderError>,
+}
+
+impl SimulatedFaults {
+    pub fn new(faults: impl IntoIterator<Item = (u64, RenderError)>) -> Self {
+        Self {
+            frame: 0,
+            schedule: faults.into_iter().collect(),
+        }
+    }
+
+    fn next(&mut self) -> Option<RenderError> {
+        self.frame += 1;
+        self.schedule.remove(&self.frame)
+    }
 }
 
 impl RenderTarget {
@@ -53,30 +171,55 @@ impl RenderTarget {
     }
 }
 
+impl Gpu {
+    fn with_device(
+        instance: wgpu::Instance,
+        surface: wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+    ) -> Self {
+        let info = adapter.get_info();
+        log::info!(
+            "using adapter '{}' ({:?}, {:?})",
+            info.name,
+            info.backend,
+            info.device_type
+        );
+        log::info!("device features: {:?}", device.features());
+        log::info!("device limits: {:?}", device.limits());
+
+        let timer = GpuTimer::new(&device, adapter.get_timestamp_period());
+        Self {
+            instance,
+            surface,
+            device,
+            queue,
+            timer,
+        }
+    }
+
+    fn begin_span(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+    ) -> Option<timing::GpuSpan> {
+        self.timer
+            .as_ref()
+            .and_then(|timer| timer.begin(encoder, label))
+    }
+
+    fn end_span(&self, encoder: &mut wgpu::CommandEncoder, span: Option<timing::GpuSpan>) {
+        if let (Some(timer), Some(span)) = (&self.timer, span) {
+            timer.end(encoder, span);
+        }
+    }
+}
+
 impl RenderState {
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(window: &Window) -> Result<Self, StartupError> {
         let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("render_state_device"),
-                    features: wgpu::Features::TEXTURE_COMPRESSION_BC,
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let gpu = request_device(window).await?;
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
@@ -86,25 +229,35 @@ impl RenderState {
             present_mode: wgpu::PresentMode::Immediate,
         };
 
-        let target = RenderTarget::new(&device, &surface, &sc_desc);
+        let target = RenderTarget::new(&gpu.device, &gpu.surface, &sc_desc);
         let target = Arc::new(Mutex::new(target));
 
-        Self {
-            surface,
-            device,
-            queue,
+        Ok(Self {
+            gpu: RwLock::new(Arc::new(gpu)),
             target,
-        }
+            windows: Mutex::new(BTreeMap::new()),
+            faults: Mutex::new(None),
+        })
+    }
+
+    /// The current device, replaced after [`RenderState::recover`] recreates it.
+    pub fn gpu(&self) -> Arc<Gpu> {
+        Arc::clone(&self.gpu.read())
+    }
+
+    pub fn simulate_faults(&self, faults: SimulatedFaults) {
+        *self.faults.lock() = Some(faults);
     }
 
     pub fn resize<T>(self: &Arc<Self>, size: Size, runner: Arc<Mutex<T>>)
     where
         T: ThreadRunner,
     {
+        let gpu = self.gpu();
         let mut target = self.target.lock();
         let mut runner = runner.lock();
-        target.resize(&self.device, &self.surface, size);
-        runner.resize(&self.device, target.size());
+        target.resize(&gpu.device, &gpu.surface, size);
+        runner.resize(&gpu.device, target.size());
     }
 
     pub fn render<T, M>(
@@ -112,41 +265,309 @@ impl RenderState {
         window: &winit::window::Window,
         thread_runner: Arc<Mutex<T>>,
         runner: &mut M,
-    ) -> Result<(), wgpu::SwapChainError>
+    ) -> Result<(), RenderError>
     where
         T: ThreadRunner,
         M: MainRunner,
     {
+        if let Some(error) = self.faults.lock().as_mut().and_then(SimulatedFaults::next) {
+            return Err(error);
+        }
+        let gpu = self.gpu();
         let target = self.target.lock();
         let frame = target.frame()?;
-        let mut encoder = self
+        let encode = timing::scope(Phase::Encode);
+        let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("render_encoder"),
             });
         {
             let mut thread_runner = thread_runner.lock();
+            let span = gpu.begin_span(&mut encoder, "thread_runner");
             thread_runner.render(
-                &self.device,
-                &self.queue,
+                &gpu.device,
+                &gpu.queue,
                 &target,
                 &frame.output.view,
                 &mut encoder,
                 window,
             );
+            gpu.end_span(&mut encoder, span);
         }
 
+        let span = gpu.begin_span(&mut encoder, "main_runner");
         runner.render(
-            &self.device,
-            &self.queue,
+            &gpu.device,
+            &gpu.queue,
             &target,
             &frame.output.view,
             &mut encoder,
             window,
         );
+        gpu.end_span(&mut encoder, span);
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(timer) = &gpu.timer {
+            timer.resolve(&mut encoder);
+        }
+        drop(encode);
+
+        {
+            let _submit = timing::scope(Phase::Submit);
+            gpu.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        if let Some(timer) = &gpu.timer {
+            timer.submitted();
+            gpu.device.poll(wgpu::Maintain::Poll);
+            timer.collect();
+        }
 
         Ok(())
     }
+
+    pub fn add_window(&self, id: WindowId, window: Window) {
+        let gpu = self.gpu();
+        let surface = unsafe { gpu.instance.create_surface(&window) };
+        let mut sc_desc = self.target.lock().sc_desc.clone();
+        let size = window.inner_size();
+        sc_desc.width = size.width;
+        sc_desc.height = size.height;
+        let target = RenderTarget::new(&gpu.device, &surface, &sc_desc);
+        self.windows.lock().insert(
+            id,
+            WindowTarget {
+                window,
+                surface,
+                target,
+            },
+        );
+    }
+
+    pub fn remove_window(&self, id: WindowId) -> bool {
+        self.windows.lock().remove(&id).is_some()
+    }
+
+    /// Finds the extra window behind a winit id.
+    pub fn window_id(&self, id: winit::window::WindowId) -> Option<WindowId> {
+        self.windows
+            .lock()
+            .iter()
+            .find(|(_, w)| w.window.id() == id)
+            .map(|(&id, _)| id)
+    }
+
+    pub fn request_redraws(&self) {
+        for w in self.windows.lock().values() {
+            w.window.request_redraw();
+        }
+    }
+
+    pub fn resize_window(&self, id: WindowId, size: Size) {
+        let gpu = self.gpu();
+        if let Some(w) = self.windows.lock().get_mut(&id) {
+            w.target.resize(&gpu.device, &w.surface, size);
+        }
+    }
+
+    /// Recreates the swap chain of a window whose surface went stale.
+    pub fn rebuild_window(&self, id: WindowId) {
+        let gpu = self.gpu();
+        if let Some(w) = self.windows.lock().get_mut(&id) {
+            let size = w.window.inner_size().into();
+            w.target.resize(&gpu.device, &w.surface, size);
+        }
+    }
+
+    pub fn render_window<T, M>(
+        self: &Arc<Self>,
+        id: WindowId,
+        thread_runner: Arc<Mutex<T>>,
+        runner: &mut M,
+    ) -> Result<(), RenderError>
+    where
+        T: ThreadRunner,
+        M: MainRunner,
+    {
+        let gpu = self.gpu();
+        let windows = self.windows.lock();
+        let w = match windows.get(&id) {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        let frame = w.target.frame()?;
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("window_encoder"),
+            });
+        thread_runner.lock().render_window(
+            id,
+            &gpu.device,
+            &gpu.queue,
+            &w.target,
+            &frame.output.view,
+            &mut encoder,
+        );
+        runner.render_window(
+            id,
+            &gpu.device,
+            &gpu.queue,
+            &w.target,
+            &frame.output.view,
+            &mut encoder,
+        );
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+
+    /// Handles an error from [`RenderState::render`]. Stale surfaces get a new
+    /// swap chain, running out of memory or losing the device recreates the
+    /// device, reloads resources and lets both runners rebuild.
+    pub fn recover<T, M>(
+        self: &Arc<Self>,
+        window: &winit::window::Window,
+        error: RenderError,
+        thread_runner: Arc<Mutex<T>>,
+        runner: &mut M,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        T: ThreadRunner,
+        M: MainRunner,
+    {
+        match error {
+            RenderError::Timeout => log::debug!("{}, skipping frame", error),
+            RenderError::Outdated | RenderError::SurfaceLost => {
+                log::info!("{}, rebuilding swap chain", error);
+                self.resize(window.inner_size().into(), thread_runner);
+            }
+            RenderError::OutOfMemory | RenderError::DeviceLost => {
+                log::warn!("{}, recreating device", error);
+                let gpu = block_on(request_device(window))?;
+                crate::resources::load(&gpu.device, &gpu.queue)?;
+
+                let mut target = self.target.lock();
+                let size = window.inner_size();
+                target.sc_desc.width = size.width;
+                target.sc_desc.height = size.height;
+                target.rebuild(&gpu.device, &gpu.surface);
+                // surfaces belong to the old instance
+                for w in self.windows.lock().values_mut() {
+                    w.surface = unsafe { gpu.instance.create_surface(&w.window) };
+                    w.target.rebuild(&gpu.device, &w.surface);
+                }
+
+                // the update thread reads the device while it holds the
+                // runner, so it never sees the old device with new resources
+                let gpu = Arc::new(gpu);
+                let mut thread_runner = thread_runner.lock();
+                *self.gpu.write() = Arc::clone(&gpu);
+                thread_runner.device_recreated(window, &gpu.device, &gpu.queue, target.sc_desc());
+                drop(thread_runner);
+                runner.device_recreated(window, &gpu.device, &gpu.queue, target.sc_desc());
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn request_device(window: &Window) -> Result<Gpu, StartupError> {
+    let mut tried = Vec::new();
+    for &(backends, power_preference) in ADAPTER_PREFERENCES.iter() {
+        let instance = wgpu::Instance::new(backends);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = match instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: Some(&surface),
+            })
+            .await
+        {
+            Some(adapter) => adapter,
+            None => {
+                log::debug!("no {:?} adapter for {:?}", power_preference, backends);
+                continue;
+            }
+        };
+
+        let info = adapter.get_info();
+        let device = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("render_state_device"),
+                    features: device_features(&info.name, adapter.features()),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await;
+        match device {
+            Ok((device, queue)) => {
+                return Ok(Gpu::with_device(instance, surface, &adapter, device, queue))
+            }
+            Err(e) => {
+                log::warn!("'{}' ({:?}): {}", info.name, info.backend, e);
+                tried.push(format!("{} ({:?})", info.name, info.backend));
+            }
+        }
+    }
+    if tried.is_empty() {
+        Err(StartupError::NoAdapter)
+    } else {
+        Err(StartupError::NoDevice { tried })
+    }
+}
+
+/// The features to request from an adapter that has `available`, any of the
+/// optional ones it lacks are left out.
+fn device_features(adapter: &str, available: wgpu::Features) -> wgpu::Features {
+    let missing = OPTIONAL_FEATURES - available;
+    if !missing.is_empty() {
+        log::warn!("'{}' doesn't support {:?}", adapter, missing);
+    }
+    available & OPTIONAL_FEATURES
+}
+
+/// Prints every adapter on every backend, for `--list-adapters`.
+pub fn list_adapters() {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+    let mut found = false;
+    for adapter in instance.enumerate_adapters(wgpu::BackendBit::all()) {
+        let info = adapter.get_info();
+        println!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+        println!("  vendor {:#06x}, device {:#06x}", info.vendor, info.device);
+        println!("  features: {:?}", adapter.features());
+        println!("  limits: {:?}", adapter.limits());
+        found = true;
+    }
+    if !found {
+        println!("no adapters found");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapter_without_bc() {
+        let available = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS;
+        assert_eq!(
+            device_features("test", available),
+            wgpu::Features::TIMESTAMP_QUERY
+        );
+        assert_eq!(
+            device_features("test", wgpu::Features::empty()),
+            wgpu::Features::empty()
+        );
+    }
+
+    #[test]
+    fn adapter_with_every_feature() {
+        assert_eq!(
+            device_features("test", wgpu::Features::all()),
+            OPTIONAL_FEATURES
+        );
+        assert!(OPTIONAL_FEATURES.contains(wgpu::Features::TEXTURE_COMPRESSION_BC));
+    }
 }
@@ -0,0 +1,123 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{event::RunnerEvent, lifecycle::Shutdown, queue::EventSender};
+
+/// One line of a recording, `tick` is the update tick whose input snapshot
+/// first contained the event.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub time: Duration,
+    pub tick: u64,
+    pub event: RunnerEvent,
+}
+
+#[derive(Serialize)]
+struct RecordedEventRef<'a> {
+    time: Duration,
+    tick: u64,
+    event: &'a RunnerEvent,
+}
+
+pub struct Recorder {
+    start: Instant,
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        log::info!("recording input to {:?}", path.as_ref());
+        Ok(Self {
+            start: Instant::now(),
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Appends `event` to the recording and flushes it. The input thread drops
+    /// the recorder when it is joined on shutdown, but a thread that misses the
+    /// shutdown deadline or a crash would otherwise lose the buffered tail.
+    pub fn record(
+        &mut self,
+        tick: u64,
+        event: &RunnerEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let recorded = RecordedEventRef {
+            time: self.start.elapsed(),
+            tick,
+            event,
+        };
+        serde_json::to_writer(&mut self.writer, &recorded)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// How long [`Replay::feed`] sleeps between checks for a shutdown.
+const DRAIN_POLL: Duration = Duration::from_millis(10);
+
+pub struct Replay {
+    events: VecDeque<RecordedEvent>,
+    queue: EventSender,
+    active: Arc<AtomicBool>,
+}
+
+impl Replay {
+    pub fn load(
+        path: impl AsRef<Path>,
+        queue: EventSender,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        log::info!("replaying input from {:?}", path.as_ref());
+        let reader = BufReader::new(File::open(path)?);
+        let mut events = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.is_empty() {
+                events.push_back(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self {
+            events,
+            queue,
+            active: Arc::new(AtomicBool::new(true)),
+        })
+    }
+
+    /// Cleared once every recorded event has been fed, after that the window's
+    /// own events are used again.
+    pub fn active(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.active)
+    }
+
+    /// Pushes the events recorded for `tick` into the input queue and waits for
+    /// the input thread to process them.
+    pub fn feed(&mut self, tick: u64, shutdown: &Shutdown) {
+        if !self.active.load(Ordering::Acquire) {
+            return;
+        }
+        while self.events.front().map_or(false, |e| e.tick <= tick) {
+            let event = self.events.pop_front().unwrap().event;
+            self.queue.send_blocking(event);
+        }
+
+        // events only count as handled once the runner has seen them, wake
+        // up now and then to notice a shutdown
+        while !shutdown.requested() && !self.queue.stats().wait_drained(DRAIN_POLL) {}
+
+        if self.events.is_empty() {
+            log::info!("replay finished at tick {}", tick);
+            self.active.store(false, Ordering::Release);
+        }
+    }
+}
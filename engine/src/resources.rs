@@ -1,4 +1,10 @@
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    num::NonZeroU8,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
@@ -6,7 +12,14 @@ use parking_lot::Mutex;
 use resources::*;
 use wgpu::{Extent3d, TextureDimension, TextureFormat, TextureUsage};
 
-use crate::graphics::texture::{Texture, TextureDescriptor};
+use crate::{
+    error::{ImageLoadError, TextureError},
+    graphics::{
+        cache::pipelines,
+        mipmap::MipGenerator,
+        texture::{SamplerDescriptor, Texture, TextureDescriptor},
+    },
+};
 
 lazy_static! {
     static ref TEXTURES: Arc<Mutex<HashMap<String, Texture>>> =
@@ -25,6 +38,12 @@ pub fn shaders() -> Arc<Mutex<HashMap<String, wgpu::ShaderModule>>> {
 
 pub fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), Box<dyn std::error::Error>> {
     log::info!("loading resources");
+    let bc = bc_supported(device);
+    if !bc {
+        log::warn!("BC compression is unsupported, decompressing textures on the CPU");
+    }
+    // anything cached was made with the previous device
+    pipelines().lock().purge();
     let resources = resources::read(&[
         "shaders.dat",
         "textures.dat"
@@ -43,31 +62,257 @@ pub fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), Box<dyn st
                     (image.size, image.depth, image.levels)
                 );
 
+                let packed = &buffer[..size];
+                let data = if bc {
+                    Cow::from(packed)
+                } else {
+                    let rgba = decompress_bc3(packed, image.size, image.depth, image.levels)?;
+                    Cow::from(rgba)
+                };
                 let texture = make_texture(
                     device,
                     queue,
-                    &buffer[..size],
+                    &data,
                     image.size,
-                    image.format,
+                    texture_format(image.format, bc),
                     image.depth,
                     image.levels,
+                    sampler_desc(&image.sampler),
                     &label,
-                );
+                )?;
                 TEXTURES.lock().insert(label, texture);
             }
-            Resource::Shader(Shader { data }) => {
-                log::info!("creating shader module {}", label);
-                let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                    label: Some(&label),
-                    source: wgpu::ShaderSource::SpirV(Cow::from(&data)),
-                    flags: wgpu::ShaderFlags::default(),
+            Resource::Shader(shader) => insert_shader(device, label, shader),
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the shader modules with the ones in `shaders.dat`, pipelines
+/// built from them are rebuilt on their next update.
+pub fn reload_shaders(device: &wgpu::Device) -> Result<(), Box<dyn std::error::Error>> {
+    log::info!("reloading shaders");
+    for ResourceItem { label, resource } in resources::read(&["shaders.dat"])? {
+        if let Resource::Shader(shader) = resource {
+            insert_shader(device, label, shader);
+        }
+    }
+    Ok(())
+}
+
+fn insert_shader(device: &wgpu::Device, label: String, Shader { data }: Shader) {
+    log::info!("creating shader module {}", label);
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(&label),
+        source: wgpu::ShaderSource::SpirV(Cow::from(&data)),
+        flags: wgpu::ShaderFlags::default(),
+    });
+    SHADERS.lock().insert(label.clone(), shader);
+    pipelines().lock().purge_shader(&label);
+}
+
+/// How [`load_image`] uploads image files.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageOptions {
+    /// `Srgb` for colors, `LinearRgb` for normal maps and other data.
+    pub format: ImageFormat,
+    /// Generates the full mip chain.
+    pub mipmaps: bool,
+    /// BC3 compresses on the CPU like `packing`, images with sides that
+    /// aren't multiples of 4 are uploaded as RGBA8 instead, as is everything
+    /// when the device doesn't support BC compression.
+    pub compress: bool,
+    pub sampler: SamplerDescriptor,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::Srgb,
+            mipmaps: true,
+            compress: false,
+            sampler: SamplerDescriptor::trilinear(),
+        }
+    }
+}
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "tga"];
+
+/// Loads a PNG, JPEG or TGA file, or a directory of same-sized ones as an
+/// array in file name order, and registers it in [`textures`] under `label`
+/// next to the packed textures. RGBA8 mips are generated on the GPU, so the
+/// packed shaders have to be loaded first.
+pub fn load_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+    path: impl AsRef<Path>,
+    options: &ImageOptions,
+) -> Result<(), ImageLoadError> {
+    let layers = read_layers(path.as_ref())?;
+    let (width, height) = layers[0].dimensions();
+    let compress = options.compress && width % 4 == 0 && height % 4 == 0 && bc_supported(device);
+    if options.compress && !compress {
+        log::warn!(
+            "{} is {}x{} or BC is unsupported, uploading it uncompressed",
+            label,
+            width,
+            height
+        );
+    }
+    log::info!(
+        "loading image: {} {:?}",
+        label,
+        (width, height, layers.len())
+    );
+
+    let texture = if compress {
+        compressed_texture(device, queue, &layers, options, label)?
+    } else {
+        rgba_texture(device, queue, &layers, options, label)?
+    };
+    TEXTURES.lock().insert(label.to_owned(), texture);
+    Ok(())
+}
+
+fn read_layers(path: &Path) -> Result<Vec<image::RgbaImage>, ImageLoadError> {
+    let paths = if path.is_dir() {
+        let mut paths = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        paths.retain(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .map_or(false, |extension| {
+                    IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                })
+        });
+        paths.sort();
+        paths
+    } else {
+        vec![path.to_owned()]
+    };
+    if paths.is_empty() {
+        return Err(ImageLoadError::Empty(path.to_owned()));
+    }
+
+    let mut layers: Vec<image::RgbaImage> = Vec::with_capacity(paths.len());
+    for path in paths {
+        log::info!("reading {:?}", path);
+        let layer = image::open(&path)?.to_rgba8();
+        if let Some(first) = layers.first() {
+            if first.dimensions() != layer.dimensions() {
+                return Err(ImageLoadError::SizeMismatch {
+                    path,
+                    expected: first.dimensions(),
+                    found: layer.dimensions(),
                 });
-                SHADERS.lock().insert(label, shader);
             }
         }
+        layers.push(layer);
     }
+    Ok(layers)
+}
 
-    Ok(())
+fn rgba_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &[image::RgbaImage],
+    options: &ImageOptions,
+    label: &str,
+) -> Result<Texture, ImageLoadError> {
+    let (width, height) = layers[0].dimensions();
+    let size = Extent3d {
+        width,
+        height,
+        depth: layers.len() as u32,
+    };
+    let levels = if options.mipmaps {
+        Texture::max_levels(size)
+    } else {
+        1
+    };
+    let texture = Texture::create_texture(
+        device,
+        &TextureDescriptor {
+            size,
+            dimension: TextureDimension::D2,
+            format: texture_format(options.format, false),
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST | TextureUsage::RENDER_ATTACHMENT,
+            samples: 1,
+            levels,
+            sampler: options.sampler,
+        },
+        Some(label),
+    )?;
+    let data: Vec<u8> = layers
+        .iter()
+        .flat_map(|layer| layer.as_raw().iter().copied())
+        .collect();
+    texture.write_data(queue, &data);
+
+    if levels > 1 {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap_encoder"),
+        });
+        MipGenerator::new(device)
+            .generate(device, &mut encoder, &texture)
+            .expect("RGBA8 textures can have mips");
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+    Ok(texture)
+}
+
+fn compressed_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &[image::RgbaImage],
+    options: &ImageOptions,
+    label: &str,
+) -> Result<Texture, ImageLoadError> {
+    use image::{
+        codecs::dxt::{DxtEncoder, DxtVariant},
+        imageops::FilterType,
+    };
+
+    let (width, height) = layers[0].dimensions();
+    // every level has to be whole 4x4 blocks
+    let blocks = |side: u32, level: u32| side >> level >= 4 && (side >> level) % 4 == 0;
+    let levels = if options.mipmaps {
+        (1..)
+            .take_while(|&level| blocks(width, level) && blocks(height, level))
+            .count() as u32
+            + 1
+    } else {
+        1
+    };
+
+    let mut data = Vec::new();
+    for layer in layers {
+        for level in 0..levels {
+            let (width, height) = (width >> level, height >> level);
+            let encoder = DxtEncoder::new(&mut data);
+            if level == 0 {
+                encoder.encode(layer.as_raw(), width, height, DxtVariant::DXT5)?;
+            } else {
+                let resized = image::imageops::resize(layer, width, height, FilterType::CatmullRom);
+                encoder.encode(resized.as_raw(), width, height, DxtVariant::DXT5)?;
+            }
+        }
+    }
+
+    Ok(make_texture(
+        device,
+        queue,
+        &data,
+        (width, height),
+        texture_format(options.format, true),
+        layers.len() as u32,
+        levels,
+        options.sampler,
+        label,
+    )?)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -76,11 +321,12 @@ fn make_texture(
     queue: &wgpu::Queue,
     data: &[u8],
     size: (u32, u32),
-    format: ImageFormat,
+    format: TextureFormat,
     depth: u32,
     levels: u32,
+    sampler: SamplerDescriptor,
     label: &str,
-) -> Texture {
+) -> Result<Texture, TextureError> {
     Texture::create_texture_with_data(
         device,
         queue,
@@ -92,14 +338,141 @@ fn make_texture(
                 depth,
             },
             dimension: TextureDimension::D2,
-            format: match dbg!(format) {
-                ImageFormat::LinearRgb => TextureFormat::Bc3RgbaUnorm,
-                ImageFormat::Srgb => TextureFormat::Bc3RgbaUnormSrgb
-            },
+            format,
             usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
             samples: 1,
             levels,
+            sampler,
         },
         Some(label),
     )
 }
+
+fn bc_supported(device: &wgpu::Device) -> bool {
+    device
+        .features()
+        .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+}
+
+fn texture_format(format: ImageFormat, compressed: bool) -> TextureFormat {
+    match (format, compressed) {
+        (ImageFormat::LinearRgb, true) => TextureFormat::Bc3RgbaUnorm,
+        (ImageFormat::Srgb, true) => TextureFormat::Bc3RgbaUnormSrgb,
+        (ImageFormat::LinearRgb, false) => TextureFormat::Rgba8Unorm,
+        (ImageFormat::Srgb, false) => TextureFormat::Rgba8UnormSrgb,
+    }
+}
+
+/// Decodes BC3 data laid out like `packing` writes it, every level of the
+/// first layer followed by the next layer, to RGBA8.
+fn decompress_bc3(
+    mut data: &[u8],
+    size: (u32, u32),
+    depth: u32,
+    levels: u32,
+) -> image::ImageResult<Vec<u8>> {
+    use image::{
+        codecs::dxt::{DxtDecoder, DxtVariant},
+        ImageDecoder,
+    };
+
+    let mut rgba = Vec::with_capacity(data.len() * 4);
+    for _ in 0..depth {
+        for level in 0..levels {
+            let decoder = DxtDecoder::new(
+                &mut data,
+                size.0 >> level,
+                size.1 >> level,
+                DxtVariant::DXT5,
+            )?;
+            let start = rgba.len();
+            rgba.resize(start + decoder.total_bytes() as usize, 0);
+            decoder.read_image(&mut rgba[start..])?;
+        }
+    }
+    Ok(rgba)
+}
+
+fn sampler_desc(info: &SamplerInfo) -> SamplerDescriptor {
+    let address_mode = |mode| match mode {
+        AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        AddressMode::Repeat => wgpu::AddressMode::Repeat,
+        AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+    };
+    let filter_mode = |mode| match mode {
+        FilterMode::Nearest => wgpu::FilterMode::Nearest,
+        FilterMode::Linear => wgpu::FilterMode::Linear,
+    };
+    let [u, v, w] = info.address_mode;
+    SamplerDescriptor {
+        address_mode_u: address_mode(u),
+        address_mode_v: address_mode(v),
+        address_mode_w: address_mode(w),
+        mag_filter: filter_mode(info.mag_filter),
+        min_filter: filter_mode(info.min_filter),
+        mipmap_filter: filter_mode(info.mipmap_filter),
+        lod_min_clamp: info.lod_min_clamp,
+        lod_max_clamp: info.lod_max_clamp,
+        compare: info.compare.map(|compare| match compare {
+            CompareFunction::Never => wgpu::CompareFunction::Never,
+            CompareFunction::Less => wgpu::CompareFunction::Less,
+            CompareFunction::Equal => wgpu::CompareFunction::Equal,
+            CompareFunction::LessEqual => wgpu::CompareFunction::LessEqual,
+            CompareFunction::Greater => wgpu::CompareFunction::Greater,
+            CompareFunction::NotEqual => wgpu::CompareFunction::NotEqual,
+            CompareFunction::GreaterEqual => wgpu::CompareFunction::GreaterEqual,
+            CompareFunction::Always => wgpu::CompareFunction::Always,
+        }),
+        anisotropy_clamp: None,
+        border_color: None,
+    }
+    .with_anisotropy(info.anisotropy.map_or(1, NonZeroU8::get))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::dxt::{DxtEncoder, DxtVariant};
+
+    use super::*;
+
+    #[test]
+    fn sampler_anisotropy() {
+        let anisotropy = |anisotropy| {
+            let info = SamplerInfo {
+                anisotropy: NonZeroU8::new(anisotropy),
+                ..Default::default()
+            };
+            sampler_desc(&info).anisotropy_clamp.map(NonZeroU8::get)
+        };
+        assert_eq!(anisotropy(0), None);
+        assert_eq!(anisotropy(8), Some(8));
+        assert_eq!(anisotropy(12), Some(8));
+        assert_eq!(anisotropy(32), Some(16));
+    }
+
+    #[test]
+    fn decompress_layers_and_levels() {
+        let colors = [[255, 0, 0, 255], [0, 0, 255, 128]];
+        let mut packed = Vec::new();
+        for color in colors.iter() {
+            for &side in [8, 4].iter() {
+                let pixels = color.repeat(side * side);
+                DxtEncoder::new(&mut packed)
+                    .encode(&pixels, side as u32, side as u32, DxtVariant::DXT5)
+                    .unwrap();
+            }
+        }
+
+        let rgba = decompress_bc3(&packed, (8, 8), 2, 2).unwrap();
+        let layer = (8 * 8 + 4 * 4) * 4;
+        assert_eq!(rgba.len(), 2 * layer);
+        for (pixels, color) in rgba.chunks(layer).zip(colors.iter()) {
+            assert!(pixels.chunks(4).all(|pixel| pixel == color));
+        }
+    }
+
+    #[test]
+    fn decompress_truncated() {
+        assert!(decompress_bc3(&[0; 16], (8, 8), 1, 1).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+const HISTORY: usize = 240;
+const GPU_QUERIES: u32 = 64;
+const GPU_READBACK_BUFFERS: usize = 3;
+
+lazy_static! {
+    static ref TIMINGS: Arc<Mutex<FrameTimings>> = Arc::new(Mutex::new(FrameTimings::new(HISTORY)));
+}
+
+pub fn timings() -> Arc<Mutex<FrameTimings>> {
+    Arc::clone(&TIMINGS)
+}
+
+/// Starts timing `phase`, the elapsed time is added to the current frame when
+/// the returned guard is dropped.
+pub fn scope(phase: Phase) -> PhaseScope {
+    PhaseScope {
+        phase,
+        start: Instant::now(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    Input,
+    Update,
+    Encode,
+    Submit,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Input, Phase::Update, Phase::Encode, Phase::Submit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Input => "input",
+            Phase::Update => "update",
+            Phase::Encode => "render encode",
+            Phase::Submit => "submit",
+        }
+    }
+}
+
+pub struct PhaseScope {
+    phase: Phase,
+    start: Instant,
+}
+
+impl Drop for PhaseScope {
+    fn drop(&mut self) {
+        TIMINGS.lock().record(self.phase, self.start.elapsed());
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimingStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimingHistory {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl TimingHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: Duration) {
+        self.samples.push_back(sample);
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Samples in milliseconds, oldest first, for plotting.
+    pub fn history_ms(&self) -> Vec<f32> {
+        self.samples
+            .iter()
+            .map(|d| d.as_secs_f32() * 1000.0)
+            .collect()
+    }
+
+    pub fn stats(&self) -> Option<TimingStats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let total: Duration = sorted.iter().sum();
+        Some(TimingStats {
+            min: sorted[0],
+            avg: total / sorted.len() as u32,
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+fn percentile(sorted: &[Duration], p: f32) -> Duration {
+    let rank = (p * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Debug)]
+pub struct FrameTimings {
+    capacity: usize,
+    last_frame: Option<Instant>,
+    pending: BTreeMap<Phase, Duration>,
+    frame: TimingHistory,
+    phases: BTreeMap<Phase, TimingHistory>,
+    gpu: BTreeMap<String, TimingHistory>,
+}
+
+impl FrameTimings {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            last_frame: None,
+            pending: BTreeMap::new(),
+            frame: TimingHistory::new(capacity),
+            phases: Phase::ALL
+                .iter()
+                .map(|&p| (p, TimingHistory::new(capacity)))
+                .collect(),
+            gpu: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `time` to `phase` for the frame currently in progress.
+    pub fn record(&mut self, phase: Phase, time: Duration) {
+        *self.pending.entry(phase).or_default() += time;
+    }
+
+    pub fn record_gpu(&mut self, label: &str, time: Duration) {
+        let capacity = self.capacity;
+        self.gpu
+            .entry(label.to_owned())
+            .or_insert_with(|| TimingHistory::new(capacity))
+            .push(time);
+    }
+
+    /// Closes the current frame, phases without any recorded work count as zero.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            self.frame.push(now - last);
+        }
+        self.last_frame = Some(now);
+        for (phase, history) in self.phases.iter_mut() {
+            history.push(self.pending.remove(phase).unwrap_or_default());
+        }
+    }
+
+    pub fn frame(&self) -> &TimingHistory {
+        &self.frame
+    }
+
+    pub fn phase(&self, phase: Phase) -> &TimingHistory {
+        &self.phases[&phase]
+    }
+
+    pub fn gpu(&self) -> impl Iterator<Item = (&str, &TimingHistory)> {
+        self.gpu.iter().map(|(l, h)| (l.as_str(), h))
+    }
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum ReadbackState {
+    Free,
+    Resolved,
+    Mapping(MapFuture),
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    spans: Vec<GpuSpan>,
+    state: ReadbackState,
+}
+
+struct GpuTimerState {
+    next: u32,
+    spans: Vec<GpuSpan>,
+    readbacks: Vec<Readback>,
+}
+
+#[derive(Debug)]
+pub struct GpuSpan {
+    label: String,
+    start: u32,
+}
+
+/// Measures GPU time between pairs of timestamp queries, results are read back
+/// a few frames later without stalling and recorded in [`timings`].
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    period: f32,
+    state: Mutex<GpuTimerState>,
+}
+
+impl GpuTimer {
+    /// Returns `None` when the device was created without timestamp queries.
+    pub fn new(device: &wgpu::Device, period: f32) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::Timestamp,
+            count: GPU_QUERIES,
+        });
+        let readbacks = (0..GPU_READBACK_BUFFERS)
+            .map(|i| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("gpu_timer_readback_{}", i)),
+                    size: (GPU_QUERIES as usize * std::mem::size_of::<u64>())
+                        as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                spans: Vec::new(),
+                state: ReadbackState::Free,
+            })
+            .collect();
+        Some(Self {
+            query_set,
+            period,
+            state: Mutex::new(GpuTimerState {
+                next: 0,
+                spans: Vec::new(),
+                readbacks,
+            }),
+        })
+    }
+
+    pub fn begin(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: impl Into<String>,
+    ) -> Option<GpuSpan> {
+        let mut state = self.state.lock();
+        if state.next + 2 > GPU_QUERIES {
+            return None;
+        }
+        let start = state.next;
+        state.next += 2;
+        encoder.write_timestamp(&self.query_set, start);
+        Some(GpuSpan {
+            label: label.into(),
+            start,
+        })
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, span: GpuSpan) {
+        encoder.write_timestamp(&self.query_set, span.start + 1);
+        self.state.lock().spans.push(span);
+    }
+
+    /// Copies this frame's queries into a free readback buffer. When all of them
+    /// are still in flight the frame's measurements are dropped.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut state = self.state.lock();
+        let count = std::mem::take(&mut state.next);
+        let spans = std::mem::take(&mut state.spans);
+        if count == 0 {
+            return;
+        }
+        if let Some(readback) = state
+            .readbacks
+            .iter_mut()
+            .find(|r| matches!(r.state, ReadbackState::Free))
+        {
+            encoder.resolve_query_set(&self.query_set, 0..count, &readback.buffer, 0);
+            readback.spans = spans;
+            readback.state = ReadbackState::Resolved;
+        } else {
+            log::debug!("gpu timer readback buffers busy, dropping frame");
+        }
+    }
+
+    /// Starts mapping buffers resolved in the command buffer that was just submitted.
+    pub fn submitted(&self) {
+        let mut state = self.state.lock();
+        for readback in state.readbacks.iter_mut() {
+            if let ReadbackState::Resolved = readback.state {
+                let mapping = readback.buffer.slice(..).map_async(wgpu::MapMode::Read);
+                readback.state = ReadbackState::Mapping(Box::pin(mapping));
+            }
+        }
+    }
+
+    /// Records every measurement whose readback has completed.
+    pub fn collect(&self) {
+        let mut state = self.state.lock();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        for readback in state.readbacks.iter_mut() {
+            let result = match &mut readback.state {
+                ReadbackState::Mapping(mapping) => match mapping.as_mut().poll(&mut cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => continue,
+                },
+                _ => continue,
+            };
+            if result.is_ok() {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                let stamps: &[u64] = bytemuck::cast_slice(&data);
+                let mut timings = TIMINGS.lock();
+                for span in readback.spans.drain(..) {
+                    let start = stamps[span.start as usize];
+                    let end = stamps[span.start as usize + 1];
+                    let nanos = end.saturating_sub(start) as f64 * self.period as f64;
+                    timings.record_gpu(&span.label, Duration::from_nanos(nanos as u64));
+                }
+                drop(data);
+                readback.buffer.unmap();
+            }
+            readback.spans.clear();
+            readback.state = ReadbackState::Free;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn empty_window() {
+        let history = TimingHistory::new(4);
+        assert!(history.is_empty());
+        assert_eq!(history.last(), None);
+        assert_eq!(history.stats(), None);
+    }
+
+    #[test]
+    fn one_sample() {
+        let mut history = TimingHistory::new(4);
+        history.push(ms(7));
+        let stats = history.stats().unwrap();
+        assert_eq!(
+            stats,
+            TimingStats {
+                min: ms(7),
+                avg: ms(7),
+                p95: ms(7),
+                p99: ms(7),
+                max: ms(7),
+            }
+        );
+    }
+
+    #[test]
+    fn percentile_bounds() {
+        let sorted: Vec<Duration> = (1..=10).map(ms).collect();
+        assert_eq!(percentile(&sorted, 0.0), ms(1));
+        assert_eq!(percentile(&sorted, 1.0), ms(10));
+        assert_eq!(percentile(&sorted, 0.5), ms(5));
+        assert_eq!(percentile(&sorted, 0.95), ms(10));
+        assert_eq!(percentile(&sorted[..1], 0.0), ms(1));
+        assert_eq!(percentile(&sorted[..1], 1.0), ms(1));
+    }
+
+    #[test]
+    fn wrapped_window() {
+        let mut history = TimingHistory::new(4);
+        for sample in [100, 200, 1, 2, 3, 4].iter() {
+            history.push(ms(*sample));
+        }
+        // the two oldest samples fell out
+        assert_eq!(history.len(), 4);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            vec![ms(1), ms(2), ms(3), ms(4)]
+        );
+        assert_eq!(history.last(), Some(ms(4)));
+        let stats = history.stats().unwrap();
+        assert_eq!(stats.min, ms(1));
+        assert_eq!(stats.max, ms(4));
+        assert_eq!(stats.avg, Duration::from_micros(2500));
+        assert_eq!(stats.p95, ms(4));
+    }
+}
@@ -1,33 +1,66 @@
-use std::{sync::Arc, thread::JoinHandle, time::Duration};
+use std::{sync::Arc, time::Duration};
 
 use parking_lot::Mutex;
 use winit::window::Window;
 
-use crate::{render::RenderState, ThreadRunner};
+use crate::{
+    inputs::{ActionMap, InputState},
+    lifecycle::{EngineThread, Shutdown},
+    render::RenderState,
+    replay::Replay,
+    timing::{self, Phase},
+    ThreadRunner,
+};
 
 const TICK_RATE: u32 = 100;
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_update_thread<T>(
     runner: Arc<Mutex<T>>,
     renderer: Arc<RenderState>,
     window: Arc<Window>,
-) -> JoinHandle<()>
+    input: Arc<Mutex<InputState>>,
+    actions: Arc<Mutex<ActionMap>>,
+    mut replay: Option<Replay>,
+    fixed_timestep: bool,
+    shutdown: Shutdown,
+) -> EngineThread
 where
     T: ThreadRunner + Send + Sync + 'static,
 {
-    std::thread::spawn(move || {
+    EngineThread::spawn("update", move || {
         let mut clock = crate::clock::Clock::new(TICK_RATE);
         let mut delta = (
             TICK_RATE as f32,
             Duration::from_secs_f32(1.0 / (TICK_RATE as f32)),
         );
-        loop {
+        while !shutdown.requested() {
             clock.tick();
-            clock.target_rate =
-                runner
-                    .lock()
-                    .update(&window, &renderer.device, &renderer.queue, delta);
-            delta = clock.wait();
+            if let Some(replay) = replay.as_mut() {
+                let tick = input.lock().tick;
+                replay.feed(tick, &shutdown);
+            }
+            let input = input.lock().snapshot();
+            clock.target_rate = {
+                let mut runner = runner.lock();
+                // after the lock, a recreated device is swapped in under it
+                let gpu = renderer.gpu();
+                let mut actions = actions.lock();
+                let _update = timing::scope(Phase::Update);
+                runner.update(
+                    &window,
+                    &gpu.device,
+                    &gpu.queue,
+                    delta,
+                    &input,
+                    &mut actions,
+                )
+            };
+            let measured = clock.wait();
+            // with a fixed timestep every tick keeps the initial delta
+            if !fixed_timestep {
+                delta = measured;
+            }
         }
     })
 }
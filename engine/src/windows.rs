@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::Size;
+
+lazy_static! {
+    static ref WINDOW_REQUESTS: Arc<Mutex<WindowRequests>> =
+        Arc::new(Mutex::new(WindowRequests::default()));
+}
+
+pub fn window_requests() -> Arc<Mutex<WindowRequests>> {
+    Arc::clone(&WINDOW_REQUESTS)
+}
+
+/// Asks the main loop to open another window. The window exists from the next
+/// event loop iteration, its events arrive as `RunnerEvent::Window` with the
+/// returned id and it is drawn through `render_window`.
+pub fn open(title: impl Into<String>, size: Size) -> WindowId {
+    WINDOW_REQUESTS.lock().open(WindowRequest {
+        title: title.into(),
+        size,
+    })
+}
+
+/// Closes a window opened with [`open`].
+pub fn close(id: WindowId) {
+    WINDOW_REQUESTS.lock().close.push(id);
+}
+
+/// Engine side window id. Unlike winit's it is the same on every run, so it
+/// can be recorded and replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct WindowId(pub u32);
+
+impl WindowId {
+    /// The window created by `engine::run`.
+    pub const MAIN: WindowId = WindowId(0);
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowRequest {
+    pub title: String,
+    pub size: Size,
+}
+
+#[derive(Debug, Default)]
+pub struct WindowRequests {
+    last: u32,
+    open: Vec<(WindowId, WindowRequest)>,
+    close: Vec<WindowId>,
+}
+
+impl WindowRequests {
+    fn open(&mut self, request: WindowRequest) -> WindowId {
+        self.last += 1;
+        let id = WindowId(self.last);
+        self.open.push((id, request));
+        id
+    }
+
+    /// Takes the windows to open and close since the last call. A window
+    /// closed before it was opened is left out of both.
+    pub fn take(&mut self) -> (Vec<(WindowId, WindowRequest)>, Vec<WindowId>) {
+        let mut open = std::mem::take(&mut self.open);
+        let mut close = std::mem::take(&mut self.close);
+        open.retain(|(id, _)| match close.iter().position(|c| c == id) {
+            Some(i) => {
+                close.swap_remove(i);
+                false
+            }
+            None => true,
+        });
+        (open, close)
+    }
+}
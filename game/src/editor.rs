@@ -3,26 +3,54 @@ use std::sync::Arc;
 use engine::{num_traits::float::FloatConst, parking_lot::Mutex, wgpu::TextureFormat};
 
 use engine::{
-    camera::Camera,
-    event::{RunnerEvent, WindowEvent},
+    camera::{
+        controller::{CameraController, ControllerInput, ControllerMode},
+        transition::{CameraState, CameraTransition, Easing},
+        Camera,
+    },
+    event::RunnerEvent,
     graphics::{
-        common::{BundleData, ItemBuffer, PipelineFormat, Renderer, RendererInvalid},
-        helper::begin_render_pass,
-        texture::Texture,
+        common::{BundleData, PipelineFormat, Renderer, RendererInvalid},
+        debug::{self, colors, DebugCommands, DebugRenderer},
+        graph::{PassDesc, PassEncoder, RenderGraph, TargetDesc, BACKBUFFER},
+        helper::calc_tangent,
+        picking::Picker,
     },
-    palette,
-    render::RenderTarget,
-    wgpu, winit,
-    winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
-    MainRunner, Size,
+    inputs::{ActionMap, Bindings, InputState},
+    palette, wgpu, winit, MainRunner, Size,
 };
 
 use crate::{
     pipelines::ico::{IcoBuffer, IcoRenderer, IcoRendererSettings, IcoUniform},
-    structures::ico::Ico,
-    ui::{EditorState, EditorUi},
+    structures::{
+        ico::{raycast::FaceTree, Ico},
+        map::{MapFile, MAP_FILE},
+    },
+    ui::{CameraRequest, EditorState, EditorUi, UiFrame},
 };
 
+/// Camera bindings that read the keyboard.
+pub const CAMERA: &str = "camera";
+/// Camera bindings that read the mouse inside the viewport.
+pub const VIEWPORT: &str = "viewport";
+
+const MAIN_PASS: &str = "main";
+const SELECT_PASS: &str = "select";
+const PICK_PASS: &str = "pick";
+const DEBUG_PASS: &str = "debug";
+const UI_PASS: &str = "ui";
+
+const DEPTH: &str = "depth";
+const SELECT: &str = "select";
+const PICKS: &str = "picks";
+
+/// Staging buffers in flight for picking.
+const PICK_SLOTS: usize = 3;
+/// Pixels around the cursor searched for a face.
+const PICK_REGION: u32 = 5;
+
+const CAMERA_TRANSITION: std::time::Duration = std::time::Duration::from_millis(600);
+
 pub struct MainGameThread {
     pub ui: EditorUi,
     pub runner: Arc<Mutex<<Self as MainRunner>::Runner>>,
@@ -30,34 +58,29 @@ pub struct MainGameThread {
 
 pub struct Editor {
     pub camera: Camera,
+    pub controller: CameraController,
+    pub transition: Option<CameraTransition>,
     pub size: Size,
 
     pub ico: Ico,
+    pub ico_tree: FaceTree,
     pub ico_buffer: IcoBuffer,
     pub ico_screen: Renderer<IcoRenderer>,
     pub ico_select: Renderer<IcoRenderer>,
 
     pub ico_uniform: IcoUniform,
 
-    pub msaa: Texture,
-    pub depth_texture: Texture,
-    pub sampled_depth_texture: Texture,
-    pub select: Texture,
-    pub select_buffer: ItemBuffer<u32>,
+    pub graph: RenderGraph,
+    pub picker: Picker,
+    pub debug: DebugRenderer,
+    /// Cursor in pixels while it's over the viewport.
+    pub cursor: Option<(u32, u32)>,
     pub selected: u32,
 
-    pub modifiers: winit::event::ModifiersState,
     pub state: EditorState,
 
     pub last_frame: std::time::Instant,
     pub delta: std::time::Duration,
-
-    pub mouse_raw: [u32; 2],
-    pub mouse_last: glam::Vec2,
-    pub mouse_pos: glam::Vec2,
-    pub mouse_pressed: bool,
-
-    pub rotating: f32,
 }
 
 impl Editor {
@@ -67,40 +90,66 @@ impl Editor {
         queue: &wgpu::Queue,
         sc_desc: &wgpu::SwapChainDescriptor,
     ) -> Self {
-        let state = EditorState::default();
+        let map = MapFile::load_or_default(MAP_FILE);
+        let mut state = EditorState::default();
+        *state.size = map.size;
+        state.size.on_change();
+        state.bookmarks = map.bookmarks;
 
         let camera = Camera::new(sc_desc, f32::FRAC_PI_2() / 2.0, *state.zoom as f32);
-        let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
-            depth: 1,
+        let graph = Self::build_graph(device, sc_desc, *state.samples as u32);
+
+        let size = Size::new(sc_desc.width, sc_desc.height);
+
+        let ico = Ico::divs(*state.size as usize);
+        let (ico_buffer, ico_screen, ico_select) =
+            Self::build_ico(device, queue, sc_desc, *state.samples as u32, &ico);
+
+        let ico_uniform = IcoUniform {
+            view_proj: camera.build(*state.perspective).into(),
+            view_pos: camera.eye(*state.perspective).into(),
+            light_pos: glam::vec3(-50.0, -50.0, -100.0).into(),
+            selected: 0,
+            s1: 0,
+            s2: 0,
+            s3: 0,
         };
 
-        let depth_texture = Texture::depth(device, size, 1, Some("depth_texture"));
-        let sampled_depth_texture = Texture::depth(
-            device,
-            size,
-            *state.samples as u32,
-            Some("depth_texture_sampled"),
-        );
-        let msaa = Texture::msaa(
-            device,
+        Self {
+            camera,
+            controller: CameraController::new(ControllerMode::Orbit),
+            transition: None,
             size,
-            *state.samples as u32,
-            Some("depth_texture_sampled"),
-        );
-        let select = Texture::select(device, size, Some("depth_texture"));
 
-        let select_buffer = select.make_buffer(
-            device,
-            wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
-        );
+            ico_tree: FaceTree::build(&ico),
+            ico,
+            ico_screen,
+            ico_select,
+            ico_uniform,
+            ico_buffer,
 
-        let size = Size::new(sc_desc.width, sc_desc.height);
+            graph,
+            picker: Picker::new(device, PICK_SLOTS, PICK_REGION),
+            debug: DebugRenderer::new(device, sc_desc, *state.samples as u32),
+            cursor: None,
+            selected: 0,
+
+            state,
+
+            delta: std::time::Duration::from_secs_f32(1.0 / 60.0),
+            last_frame: std::time::Instant::now(),
+        }
+    }
 
+    fn build_ico(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        samples: u32,
+        ico: &Ico,
+    ) -> (IcoBuffer, Renderer<IcoRenderer>, Renderer<IcoRenderer>) {
         let mut ico_buffer = IcoBuffer::build(device);
-        let ico = Ico::divs(*state.size as usize);
-        ico_buffer.update(device, queue, &ico);
+        ico_buffer.update(device, queue, ico);
 
         let ico_screen: Renderer<IcoRenderer> = Renderer::new(
             &IcoRendererSettings {
@@ -109,7 +158,7 @@ impl Editor {
             },
             device,
             sc_desc.into(),
-            *state.samples as u32,
+            samples,
             ico_buffer.clone(),
         );
 
@@ -125,145 +174,83 @@ impl Editor {
             1,
             ico_buffer.clone(),
         );
+        (ico_buffer, ico_screen, ico_select)
+    }
 
-        let ico_uniform = IcoUniform {
-            view_proj: camera.build(*state.perspective).into(),
-            view_pos: (camera.rot * -camera.zoom).into(),
-            light_pos: glam::vec3(-50.0, -50.0, -100.0).into(),
-            selected: 0,
-            s1: 0,
-            s2: 0,
-            s3: 0,
-        };
-
-        let modifiers = winit::event::ModifiersState::default();
-
-        let mouse_raw = [0; 2];
-        let mouse_last = [0.0; 2].into();
-        let mouse_pos = [0.0; 2].into();
-        let mouse_pressed = false;
-
-        Self {
-            camera,
-            size,
-
-            ico,
-            ico_screen,
-            ico_select,
-            ico_uniform,
-            ico_buffer,
-
-            msaa,
-            depth_texture,
-            sampled_depth_texture,
-
-            select,
-            select_buffer,
-            selected: 0,
-
-            state,
-            modifiers,
-
-            delta: std::time::Duration::from_secs_f32(1.0 / 60.0),
-            last_frame: std::time::Instant::now(),
+    fn build_graph(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        samples: u32,
+    ) -> RenderGraph {
+        let color: palette::LinSrgb<f64> =
+            palette::rgb::Srgb::from_components((0.53, 0.81, 0.92)).into_linear();
+        let clear = wgpu::LoadOp::Clear(wgpu::Color {
+            r: color.red,
+            g: color.green,
+            b: color.blue,
+            a: 1.0,
+        });
+
+        let mut graph = RenderGraph::new(sc_desc);
+        graph
+            .add_target(DEPTH, TargetDesc::depth())
+            .add_target(
+                SELECT,
+                TargetDesc::color(TextureFormat::R32Uint).with_usage(wgpu::TextureUsage::COPY_SRC),
+            )
+            .add_pass(
+                PassDesc::render(MAIN_PASS)
+                    .color(BACKBUFFER, clear)
+                    .depth(DEPTH, wgpu::LoadOp::Clear(1.0))
+                    .samples(samples),
+            )
+            .add_pass(
+                PassDesc::render(DEBUG_PASS)
+                    .color(BACKBUFFER, wgpu::LoadOp::Load)
+                    .depth(DEPTH, wgpu::LoadOp::Load)
+                    .samples(samples),
+            )
+            // drawn over whatever the editor rendered into the frame
+            .add_pass(PassDesc::render(UI_PASS).color(BACKBUFFER, wgpu::LoadOp::Load))
+            .add_pass(
+                PassDesc::render(SELECT_PASS)
+                    .color(SELECT, clear)
+                    .depth(DEPTH, wgpu::LoadOp::Clear(1.0)),
+            )
+            .add_pass(PassDesc::commands(PICK_PASS).read(SELECT).write(PICKS))
+            .add_output(PICKS);
+        graph.compile(device).expect("invalid editor render graph");
+        graph
+    }
 
-            mouse_raw,
-            mouse_last,
-            mouse_pos,
-            mouse_pressed,
+    /// Rebuilds every GPU resource on a new device, keeping the editor state.
+    pub fn device_recreated(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+    ) {
+        let (ico_buffer, ico_screen, ico_select) = Self::build_ico(
+            device,
+            queue,
+            sc_desc,
+            *self.state.samples as u32,
+            &self.ico,
+        );
+        self.ico_buffer = ico_buffer;
+        self.ico_screen = ico_screen;
+        self.ico_select = ico_select;
+        self.picker = Picker::new(device, PICK_SLOTS, PICK_REGION);
+        self.debug = DebugRenderer::new(device, sc_desc, *self.state.samples as u32);
+        self.resize(device, Size::new(sc_desc.width, sc_desc.height));
+    }
 
-            rotating: 0.0,
-        }
+    pub fn bindings() -> Bindings {
+        CameraController::bindings(VIEWPORT, CAMERA)
     }
 
     pub fn input(&mut self, event: RunnerEvent) -> bool {
         match event {
-            RunnerEvent::Window(event) => match event {
-                WindowEvent::CursorMoved { position, .. } => {
-                    self.mouse_pos = [
-                        (2.0 * position.0 as f32 / self.size.width as f32) - 1.0,
-                        (2.0 * position.1 as f32 / self.size.height as f32) - 1.0,
-                    ]
-                    .into();
-                    self.mouse_raw = [position.0 as u32, position.1 as u32];
-                    true
-                }
-                WindowEvent::MouseInput {
-                    state: winit::event::ElementState::Pressed,
-                    button: winit::event::MouseButton::Left,
-                    ..
-                } => {
-                    self.mouse_last = self.mouse_pos;
-                    self.mouse_pressed = true;
-                    true
-                }
-                WindowEvent::MouseWheel {
-                    delta: winit::event::MouseScrollDelta::LineDelta(_, scroll),
-                    phase: winit::event::TouchPhase::Moved,
-                    ..
-                } => {
-                    *self.state.zoom += scroll * -0.1;
-                    if *self.state.zoom < 0.1 {
-                        *self.state.zoom = 0.1
-                    }
-                    if *self.state.zoom > 2.0 {
-                        *self.state.zoom = 2.0
-                    }
-                    true
-                }
-                WindowEvent::MouseInput {
-                    state: winit::event::ElementState::Released,
-                    button: winit::event::MouseButton::Left,
-                    ..
-                } => {
-                    self.mouse_pressed = false;
-                    true
-                }
-                WindowEvent::ModifiersChanged(m) => {
-                    self.modifiers = m;
-                    true
-                }
-                _ => false,
-            },
-            RunnerEvent::Device(event) => match event {
-                winit::event::DeviceEvent::Key(KeyboardInput {
-                    virtual_keycode: Some(VirtualKeyCode::Q),
-                    state: ElementState::Pressed,
-                    ..
-                }) => {
-                    self.rotating = -1.275 * self.delta.as_secs_f32();
-                    true
-                }
-                winit::event::DeviceEvent::Key(KeyboardInput {
-                    virtual_keycode: Some(VirtualKeyCode::E),
-                    state: ElementState::Pressed,
-                    ..
-                }) => {
-                    self.rotating = 1.275 * self.delta.as_secs_f32();
-                    true
-                }
-                winit::event::DeviceEvent::Key(KeyboardInput {
-                    virtual_keycode: Some(VirtualKeyCode::Q),
-                    state: ElementState::Released,
-                    ..
-                }) => {
-                    if self.rotating < 0.0 {
-                        self.rotating = 0.0;
-                    }
-                    true
-                }
-                winit::event::DeviceEvent::Key(KeyboardInput {
-                    virtual_keycode: Some(VirtualKeyCode::E),
-                    state: ElementState::Released,
-                    ..
-                }) => {
-                    if self.rotating > 0.0 {
-                        self.rotating = 0.0;
-                    }
-                    true
-                }
-                _ => false,
-            },
             RunnerEvent::RenderComplete {
                 frame_time,
                 tick_rate,
@@ -272,136 +259,251 @@ impl Editor {
                 self.state.fps = tick_rate;
                 false
             }
-            RunnerEvent::None => false,
+            _ => false,
         }
     }
 
     pub fn resize(&mut self, device: &wgpu::Device, size: Size) {
         self.size = size;
-        self.msaa = self.msaa.with_size(device, self.size);
-        self.depth_texture = self.depth_texture.with_size(device, self.size);
-        self.sampled_depth_texture = self.sampled_depth_texture.with_size(device, self.size);
-        self.select = self.select.with_size(device, self.size);
-
-        self.select_buffer = self.select.make_buffer(
-            device,
-            wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
-        );
+        self.graph.resize(device, size);
         self.camera.resize(size);
     }
 
+    fn camera_request(&mut self, request: CameraRequest) {
+        let perspective = *self.state.perspective;
+        match request {
+            CameraRequest::SaveBookmark(name) => {
+                let state = CameraState::of(&self.camera, perspective);
+                self.state.bookmarks.save(name, state);
+                self.save_map();
+            }
+            CameraRequest::GotoBookmark(name) => {
+                if let Some(&state) = self.state.bookmarks.get(&name) {
+                    self.animate_to(state);
+                }
+            }
+            CameraRequest::RemoveBookmark(name) => {
+                self.state.bookmarks.remove(&name);
+                self.save_map();
+            }
+            CameraRequest::FrameSelected => {
+                if let Some(face) = self.ico.face(self.selected) {
+                    let framing = CameraState::framing(
+                        &self.camera,
+                        perspective,
+                        face.vertices.iter().copied(),
+                        face.normal,
+                        self.controller.zoom_range(),
+                    );
+                    if let Some(state) = framing {
+                        self.animate_to(state);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn animate_to(&mut self, to: CameraState) {
+        let from = CameraState::of(&self.camera, *self.state.perspective);
+        self.transition = Some(CameraTransition::new(
+            from,
+            to,
+            CAMERA_TRANSITION,
+            Easing::EaseInOutCubic,
+        ));
+    }
+
+    pub fn save_map(&self) {
+        let map = MapFile {
+            size: *self.state.size,
+            bookmarks: self.state.bookmarks.clone(),
+        };
+        if let Err(e) = map.save(MAP_FILE) {
+            log::warn!("failed to save {}: {}", MAP_FILE, e);
+        }
+    }
+
     pub fn update(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         _window: &winit::window::Window,
+        input: &InputState,
+        actions: &mut ActionMap,
     ) {
+        let wants_mouse = {
+            let ui_io = self.state.ui_io.lock();
+            actions.set_context(CAMERA, !ui_io.wants_keyboard);
+            actions.set_context(VIEWPORT, !ui_io.wants_mouse);
+            ui_io.wants_mouse
+        };
+        let actions = actions.state(input);
+
+        self.cursor = match input.cursor {
+            Some(cursor) if !wants_mouse => Some((cursor.x as u32, cursor.y as u32)),
+            _ => None,
+        };
+        if let Some(pick) = self.picker.poll(device) {
+            // keep the last face when the cursor moved onto the ui
+            if self.cursor.is_some() && !self.state.cpu_picking {
+                self.selected = pick.id;
+            }
+        }
+
         if let Some(&samples) = self.state.samples.on_change() {
-            self.sampled_depth_texture = self
-                .sampled_depth_texture
-                .with_samples(device, samples as u32);
-            self.msaa = self.msaa.with_samples(device, samples as u32);
+            self.graph
+                .set_samples(device, MAIN_PASS, samples as u32)
+                .unwrap();
+            self.graph
+                .set_samples(device, DEBUG_PASS, samples as u32)
+                .unwrap();
 
             self.ico_screen.invalid(RendererInvalid::Pipeline);
         }
 
-        self.camera.zoom = *self.state.zoom;
-        if self.mouse_pressed && !self.state.ui_io.lock().wants_mouse {
-            self.camera.pan(self.mouse_pos - self.mouse_last, 2.0);
+        if let Some(&zoom) = self.state.zoom.on_change() {
+            self.controller.zoom_to(zoom);
         }
-        self.camera.rotate(self.rotating);
-        self.mouse_last = self.mouse_pos;
+        if let Some(request) = self.state.camera_request.take() {
+            self.camera_request(request);
+        }
+        let camera_input = ControllerInput::from_actions(&actions, input);
+        if camera_input.drag.is_some() {
+            self.transition = None;
+        }
+        if let Some(transition) = self.transition.as_mut() {
+            let state = transition.update(self.state.tick_time);
+            *self.state.perspective = state.apply(&mut self.camera);
+            if transition.finished() {
+                self.transition = None;
+            }
+            self.controller.stop();
+        } else {
+            self.controller.update(
+                &mut self.camera,
+                &camera_input,
+                self.state.tick_time.as_secs_f32(),
+            );
+        }
+        *self.state.zoom = self.camera.zoom;
+        self.state.zoom.on_change();
 
         let view_proj = self.camera.build(*self.state.perspective);
 
-        if let Some(&d) = self.state.size.on_change() {
+        let resized = self.state.size.on_change().copied();
+        if let Some(d) = resized {
             let ico = Ico::divs(d as usize);
             self.ico = ico;
+            self.ico_tree = FaceTree::build(&self.ico);
             self.ico_buffer.update(device, queue, &self.ico);
         }
-
-        // let index = ((sc_desc.width * self.mouse_raw[1]) + self.mouse_raw[0])
-        //     .min(sc_desc.width * sc_desc.height - 1) as wgpu::BufferAddress;
-        // let new = block_on(self.select_buffer.mapped_read(device, index));
-
-        // self.select_buffer.buffer().unmap();
+        let debug_ico = self.state.debug_ico.on_change().copied();
+        if debug_ico.is_some() || (resized.is_some() && *self.state.debug_ico) {
+            if *self.state.debug_ico {
+                debug::submit("ico", Self::debug_ico(&self.ico));
+            } else {
+                debug::clear("ico");
+            }
+        }
+        if self.state.cpu_picking {
+            if let Some((x, y)) = self.cursor {
+                let ray = self.camera.screen_point_to_ray(
+                    glam::vec2(x as f32, y as f32),
+                    self.size,
+                    *self.state.perspective,
+                );
+                self.selected = self
+                    .ico_tree
+                    .raycast(&self.ico, &ray)
+                    .map_or(0, |hit| hit.face);
+            }
+        }
 
         self.ico_uniform.view_proj = view_proj.into();
-        self.ico_uniform.view_pos = (self.camera.rot * -self.camera.zoom).into();
+        self.ico_uniform.view_pos = self.camera.eye(*self.state.perspective).into();
         self.ico_uniform.selected = self.selected;
         self.ico_screen
             .renderer
-            .uniform_binding
+            .uniform
             .update(queue, self.ico_uniform);
         self.ico_select
             .renderer
-            .uniform_binding
+            .uniform
             .update(queue, self.ico_uniform);
 
+        let mut selected = DebugCommands::new();
+        if let Some(face) = self.ico.face(self.selected) {
+            let center = face.vertices.iter().sum::<glam::Vec3>() / 3.0;
+            selected.text(center, format!("#{}", self.selected), colors::WHITE);
+        }
+        debug::submit("selected", selected);
+
+        self.debug
+            .prepare(device, queue, view_proj, *self.state.samples as u32);
+        self.state.markers = debug::screen_markers(view_proj, self.size);
+
         self.ico_screen.update(device, *self.state.samples as u32);
         self.ico_select.update(device, 1);
     }
 
+    /// The normal, tangent and bitangent of every face, and links halfway to
+    /// its siblings.
+    fn debug_ico(ico: &Ico) -> DebugCommands {
+        let center = |vertices: &[glam::Vec3; 3]| vertices.iter().sum::<glam::Vec3>() / 3.0;
+        let mut commands = DebugCommands::new();
+        for face in ico.faces() {
+            let [a, b, c] = face.vertices;
+            let at = center(&face.vertices);
+            let size = (a.distance(b) + b.distance(c) + c.distance(a)) / 6.0;
+            let (tangent, bitangent) = calc_tangent(face.vertices, face.tex_coords);
+            commands
+                .point(at, size * 0.2, colors::WHITE)
+                .arrow(at, at + face.normal * size, colors::BLUE)
+                .arrow(at, at + tangent.normalize() * size, colors::RED)
+                .arrow(at, at + bitangent.normalize() * size, colors::GREEN);
+            for sibling in face.siblings.iter().flatten() {
+                if let Some(sibling) = ico.face(sibling.get()) {
+                    let to = center(&sibling.vertices);
+                    commands.line(at, at.lerp(to, 0.5), colors::YELLOW);
+                }
+            }
+        }
+        commands
+    }
+
+    /// Records the editor graph, its ui pass draws `ui` over the frame.
     pub fn render(
         &mut self,
-        _device: &wgpu::Device,
-        _queue: &wgpu::Queue,
-        target: &RenderTarget,
         frame: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
-        _window: &winit::window::Window,
+        ui: UiFrame<'_>,
     ) {
-        let color = palette::rgb::Srgb::from_components((0.53, 0.81, 0.92)).into_linear();
-        let msaa = if *self.state.samples == 1 {
-            None
-        } else {
-            Some(&self.msaa)
-        };
-        let size = target.size();
-        {
-            let mut pass = begin_render_pass(
-                encoder,
-                &frame,
-                Some(&self.sampled_depth_texture),
-                color,
-                msaa,
-                Some("main_render_pass"),
-            );
-            pass.execute_bundles(vec![&self.ico_screen.bundle].into_iter());
-        }
-
-        {
-            let mut pass = begin_render_pass(
-                encoder,
-                &self.select.view,
-                Some(&self.depth_texture),
-                color,
-                None,
-                Some("select_render_pass"),
-            );
-            pass.execute_bundles(vec![&self.ico_select.bundle].into_iter());
-        }
-
-        encoder.copy_texture_to_buffer(
-            wgpu::TextureCopyView {
-                texture: &self.select.texture,
-                mip_level: 0,
-                origin: Default::default(),
-            },
-            wgpu::BufferCopyView {
-                buffer: &self.select_buffer.buffer(),
-                layout: wgpu::TextureDataLayout {
-                    offset: 0,
-                    bytes_per_row: (self.select_buffer.num_items() * std::mem::size_of::<u32>())
-                        as u32
-                        / size.height,
-                    rows_per_image: size.height,
-                },
-            },
-            wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth: 1,
+        let graph = &self.graph;
+        let picker = &mut self.picker;
+        let cursor = self.cursor.filter(|_| !self.state.cpu_picking);
+        let mut bundles = (
+            &self.ico_screen.bundle,
+            &self.ico_select.bundle,
+            &self.debug,
+            ui,
+        );
+        graph.execute(
+            encoder,
+            frame,
+            &mut bundles,
+            |bundles, pass, encoder| match (pass, encoder) {
+                (MAIN_PASS, PassEncoder::Render(pass)) => {
+                    pass.execute_bundles(std::iter::once(bundles.0))
+                }
+                (SELECT_PASS, PassEncoder::Render(pass)) => {
+                    pass.execute_bundles(std::iter::once(bundles.1))
+                }
+                (DEBUG_PASS, PassEncoder::Render(pass)) => bundles.2.draw(pass),
+                (UI_PASS, PassEncoder::Render(pass)) => bundles.3.record(pass),
+                (PICK_PASS, PassEncoder::Commands(encoder)) => {
+                    picker.record(encoder, graph.target(SELECT).unwrap(), cursor)
+                }
+                _ => {}
             },
         );
     }
@@ -3,8 +3,11 @@ use std::{sync::Arc, time::Duration};
 use editor::{Editor, MainGameThread};
 
 use engine::{
-    event::RunnerEvent, parking_lot::Mutex, render::RenderTarget, wgpu, winit, MainRunner, Size,
-    ThreadRunner,
+    event::RunnerEvent,
+    inputs::{ActionMap, Bindings, InputState},
+    parking_lot::Mutex,
+    render::RenderTarget,
+    wgpu, winit, MainRunner, Size, ThreadRunner,
 };
 
 use ui::EditorUi;
@@ -32,6 +35,10 @@ impl MainRunner for MainGameThread {
         Self { ui, runner }
     }
 
+    fn default_bindings() -> Bindings {
+        Editor::bindings()
+    }
+
     fn global_event(
         &mut self,
         event: &winit::event::Event<RunnerEvent>,
@@ -68,7 +75,19 @@ impl MainRunner for MainGameThread {
     ) {
         let mut runner = self.runner.lock();
         self.ui
-            .render(&mut runner.state, frame, encoder, queue, device, window)
+            .render(&mut runner, frame, encoder, queue, device, window)
+    }
+
+    fn device_recreated(
+        &mut self,
+        _window: &winit::window::Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+    ) {
+        let mut runner = self.runner.lock();
+        self.ui
+            .device_recreated(device, queue, sc_desc, &mut runner.state);
     }
 }
 
@@ -104,26 +123,46 @@ impl ThreadRunner for Editor {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         delta: (f32, Duration),
+        input: &InputState,
+        actions: &mut ActionMap,
     ) -> u32 {
         self.state.tick_rate = delta.0;
         self.state.tick_time = delta.1;
-        self.update(device, queue, window);
+        self.update(device, queue, window, input, actions);
         self.state.target_tick_rate
     }
 
+    /// The editor graph is recorded by `MainGameThread::render`, together
+    /// with the ui pass.
     fn render(
         &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _target: &RenderTarget,
+        _frame: &wgpu::TextureView,
+        _encoder: &mut wgpu::CommandEncoder,
+        _window: &winit::window::Window,
+    ) {
+    }
+
+    fn device_recreated(
+        &mut self,
+        _window: &winit::window::Window,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        target: &RenderTarget,
-        frame: &wgpu::TextureView,
-        encoder: &mut wgpu::CommandEncoder,
-        window: &winit::window::Window,
+        sc_desc: &wgpu::SwapChainDescriptor,
     ) {
-        self.render(device, queue, target, frame, encoder, window)
+        self.device_recreated(device, queue, sc_desc)
+    }
+
+    fn shutdown(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {
+        self.save_map();
     }
 }
 
-fn main() -> Result<(), std::boxed::Box<(dyn std::error::Error)>> {
-    engine::run::<MainGameThread>()
+fn main() {
+    if let Err(e) = engine::run::<MainGameThread>() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
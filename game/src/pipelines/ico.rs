@@ -1,12 +1,15 @@
+use std::sync::Arc;
+
 use crevice::std140::AsStd140;
 
 use engine::{
     graphics::{
+        binding::{BindGroupBuilder, BindResource, Bindings},
         common::{
-            BundleData, ItemBuffer, Pipeline, PipelineFormat, PipelineSettings, TextureBinding,
-            UniformBinding,
+            BundleBuffers, BundleData, ItemBuffer, Pipeline, PipelineFormat, PipelineSettings,
+            UniformBinding, UniformBuffer,
         },
-        helper::{create_buffer, create_pipeline, create_texture_binding, create_uniform_binding},
+        helper::{create_buffer, create_pipeline, create_uniform_buffer},
         texture::Texture,
     },
     wgpu,
@@ -49,6 +52,10 @@ impl BundleData for IcoBuffer {
     fn id(&self) -> Self::Id {
         self.vertex_buffer.id()
     }
+
+    fn buffers(&self) -> BundleBuffers<'_> {
+        BundleBuffers::vertices(&self.vertex_buffer)
+    }
 }
 
 #[repr(C)]
@@ -128,9 +135,9 @@ pub struct IcoRendererSettings {
 }
 
 pub struct IcoRenderer {
-    pub texture_binding: TextureBinding,
-    pub normal_binding: TextureBinding,
-    pub uniform_binding: UniformBinding<IcoUniform>,
+    /// The uniform, the face textures and the stitch normal map.
+    pub bindings: Bindings,
+    pub uniform: UniformBuffer<IcoUniform>,
     pub vs: &'static str,
     pub fs: &'static str,
 }
@@ -144,13 +151,9 @@ impl Pipeline for IcoRenderer {
         device: &wgpu::Device,
         format: PipelineFormat,
         samples: u32,
-    ) -> wgpu::RenderPipeline {
+    ) -> Arc<wgpu::RenderPipeline> {
         let settings = PipelineSettings {
-            layouts: &[
-                &self.uniform_binding.layout,
-                &self.texture_binding.layout.layout,
-                &self.normal_binding.layout.layout,
-            ],
+            layouts: &[&self.bindings.layout],
             buffers: &[IcoVertex::desc()],
             samples,
             ..Default::default()
@@ -167,6 +170,7 @@ impl Pipeline for IcoRenderer {
         samples: u32,
         data: &IcoBuffer,
     ) -> wgpu::RenderBundle {
+        let buffers = data.buffers();
         let mut bundle =
             device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
                 label: Some("ico_render_bundle"),
@@ -175,14 +179,9 @@ impl Pipeline for IcoRenderer {
                 sample_count: samples,
             });
 
-        let vb = data.vertex_buffer.buffer();
-
         bundle.set_pipeline(pipeline);
-        bundle.set_bind_group(0, &self.uniform_binding.binding, &[]);
-        bundle.set_bind_group(1, &self.texture_binding.binding, &[]);
-        bundle.set_bind_group(2, &self.normal_binding.binding, &[]);
-        bundle.set_vertex_buffer(0, vb.slice(..));
-        bundle.draw(0..data.vertex_buffer.num_items() as u32, 0..1);
+        bundle.set_bind_group(0, &self.bindings.binding, &[]);
+        buffers.record(&mut bundle);
         bundle.finish(&wgpu::RenderBundleDescriptor {
             label: Some("ico_render_bundle"),
         })
@@ -194,14 +193,25 @@ impl Pipeline for IcoRenderer {
         let tex_lock = tex_store.lock();
         let textures = tex_lock.get("ico_textures").expect("texture not found");
         let normal = tex_lock.get("ico_stitch_map").expect("texture not found");
-        let texture_binding = create_texture_binding(device, textures, Some("ico"));
-        let normal_binding = create_texture_binding(device, normal, Some("stitch"));
-        let uniform_binding: UniformBinding<IcoUniform> =
-            create_uniform_binding(device, Some("ico"));
+        let uniform: UniformBuffer<IcoUniform> = create_uniform_buffer(device, Some("ico"));
+        let bindings = BindGroupBuilder::new()
+            .uniform(wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT)
+            .sampled_texture(wgpu::ShaderStage::FRAGMENT, textures)
+            .sampled_texture(wgpu::ShaderStage::FRAGMENT, normal)
+            .build(
+                device,
+                &[
+                    BindResource::Buffer(&uniform.buffer),
+                    BindResource::Texture(textures),
+                    BindResource::Sampler(&textures.sampler),
+                    BindResource::Texture(normal),
+                    BindResource::Sampler(&normal.sampler),
+                ],
+                Some("ico"),
+            );
         Self {
-            texture_binding,
-            normal_binding,
-            uniform_binding,
+            bindings,
+            uniform,
             vs,
             fs,
         }
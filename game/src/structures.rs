@@ -1 +1,2 @@
 pub mod ico;
+pub mod map;
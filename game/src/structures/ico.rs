@@ -7,6 +7,8 @@ use rand::prelude::*;
 
 use crate::pipelines::ico::IcoVertex;
 
+pub mod raycast;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct IcoFace {
@@ -242,6 +244,10 @@ impl Ico {
             .collect()
     }
 
+    pub fn faces(&self) -> &[IcoFace] {
+        &self.faces
+    }
+
     pub fn face(&self, index: u32) -> Option<&IcoFace> {
         if index == 0 {
             return None;
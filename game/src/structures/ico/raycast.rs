@@ -0,0 +1,252 @@
+use engine::camera::Ray;
+use glam::Vec3;
+
+use super::{Ico, IcoFace};
+
+/// Where a ray meets an [`Ico`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IcoHit {
+    /// [`IcoFace::index`] of the face hit, the same id GPU picking reads.
+    pub face: u32,
+    /// Weights of the face's three vertices at `point`.
+    pub barycentric: Vec3,
+    pub point: Vec3,
+    /// Distance along the ray.
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Bounds {
+    fn of([a, b, c]: [Vec3; 3]) -> Self {
+        Self {
+            min: a.min(b).min(c),
+            max: a.max(b).max(c),
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Whether `ray` passes through before `limit`, `inverse` is the
+    /// reciprocal of its direction.
+    fn hit(&self, ray: &Ray, inverse: Vec3, limit: f32) -> bool {
+        let t0 = (self.min - ray.origin) * inverse;
+        let t1 = (self.max - ray.origin) * inverse;
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element();
+        near <= far && near <= limit
+    }
+}
+
+/// Bounding boxes of the faces of every subdivision level. Face `n` of a
+/// level is split into faces `4n - 3` to `4n` of the next, so the levels form
+/// a quadtree under the 20 base faces and a ray only visits the branches it
+/// passes through.
+pub struct FaceTree {
+    /// From the base faces down to the faces of the [`Ico`].
+    levels: Vec<Vec<Bounds>>,
+}
+
+impl FaceTree {
+    pub fn build(ico: &Ico) -> Self {
+        let leaves = ico.faces.iter().map(|f| Bounds::of(f.vertices)).collect();
+        let mut levels: Vec<Vec<Bounds>> = vec![leaves];
+        for _ in 0..ico.sub {
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(4)
+                .map(|children| children[1..].iter().fold(children[0], |a, &b| a.merge(b)))
+                .collect();
+            levels.push(parents);
+        }
+        levels.reverse();
+        Self { levels }
+    }
+
+    /// The closest face of `ico` hit by `ray`. `ico` has to be the one the
+    /// tree was built from.
+    pub fn raycast(&self, ico: &Ico, ray: &Ray) -> Option<IcoHit> {
+        let inverse = ray.direction.recip();
+        let leaf = self.levels.len() - 1;
+        let mut closest: Option<IcoHit> = None;
+        let mut stack: Vec<(usize, u32)> =
+            (1..=self.levels[0].len() as u32).map(|n| (0, n)).collect();
+        while let Some((level, n)) = stack.pop() {
+            let limit = closest.map_or(f32::INFINITY, |hit| hit.distance);
+            if !self.levels[level][(n - 1) as usize].hit(ray, inverse, limit) {
+                continue;
+            }
+            if level < leaf {
+                stack.extend((n * 4 - 3..=n * 4).map(|child| (level + 1, child)));
+                continue;
+            }
+            let hit = ico.face(n).and_then(|face| intersect(ray, face));
+            if let Some(hit) = hit.filter(|hit| hit.distance < limit) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+}
+
+/// Möller–Trumbore, hits either side of the face.
+fn intersect(ray: &Ray, face: &IcoFace) -> Option<IcoHit> {
+    let [a, b, c] = face.vertices;
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = e2.dot(q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    Some(IcoHit {
+        face: face.index.get(),
+        barycentric: Vec3::new(1.0 - u - v, u, v),
+        point: ray.at(distance),
+        distance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::{camera::Camera, Size};
+
+    use super::*;
+
+    fn center(face: &IcoFace) -> Vec3 {
+        face.vertices.iter().sum::<Vec3>() / 3.0
+    }
+
+    /// Looking at `point` from outside the ico.
+    fn camera_facing(point: Vec3) -> Camera {
+        let rot = point.normalize();
+        Camera {
+            rot,
+            target: Vec3::ZERO,
+            up: rot.any_orthonormal_vector(),
+            aspect: 1.0,
+            fovy: std::f32::consts::FRAC_PI_2,
+            zoom: 1.0,
+        }
+    }
+
+    /// Rays from all around the ico towards points scattered inside it.
+    fn rays() -> impl Iterator<Item = Ray> {
+        (0..200).map(|i| {
+            let i = i as f32;
+            let from = glam::vec3((i * 0.7).sin(), (i * 1.3).cos(), (i * 2.9).sin());
+            let to = glam::vec3((i * 3.1).cos(), (i * 0.3).sin(), (i * 1.7).cos()) * 0.5;
+            let origin = from.normalize() * 3.0;
+            Ray {
+                origin,
+                direction: (to - origin).normalize(),
+            }
+        })
+    }
+
+    #[test]
+    fn agrees_with_gpu_picking() {
+        let size = Size::new(800, 800);
+        for &sub in [0, 1, 3].iter() {
+            let ico = Ico::divs(sub);
+            let tree = FaceTree::build(&ico);
+            // the select pass writes the index of the vertices of each face
+            let ids: Vec<u32> = ico.vertex_data().chunks(3).map(|v| v[0].index).collect();
+            for (face, &id) in ico.faces().iter().zip(ids.iter()).step_by(7) {
+                let center = center(face);
+                let camera = camera_facing(center);
+                for &perspective in [true, false].iter() {
+                    let pixel = camera
+                        .world_to_screen(center, size, perspective)
+                        .unwrap()
+                        .truncate();
+                    let ray = camera.screen_point_to_ray(pixel, size, perspective);
+                    let hit = tree.raycast(&ico, &ray).expect("ray missed the ico");
+                    assert_eq!(hit.face, id, "sub {}, perspective {}", sub, perspective);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn barycentric_weights() {
+        let ico = Ico::divs(2);
+        let tree = FaceTree::build(&ico);
+        for ray in rays() {
+            let hit = tree.raycast(&ico, &ray).unwrap();
+            let b = hit.barycentric;
+            assert!((b.x + b.y + b.z - 1.0).abs() < 1e-5);
+            assert!(b.min_element() >= 0.0);
+            let [p0, p1, p2] = ico.face(hit.face).unwrap().vertices;
+            let point = p0 * b.x + p1 * b.y + p2 * b.z;
+            assert!(point.abs_diff_eq(hit.point, 1e-4));
+        }
+    }
+
+    #[test]
+    fn misses() {
+        let ico = Ico::divs(2);
+        let tree = FaceTree::build(&ico);
+        let away = Ray {
+            origin: glam::vec3(0.0, 0.0, 3.0),
+            direction: Vec3::Z,
+        };
+        let beside = Ray {
+            origin: glam::vec3(-3.0, 1.5, 0.0),
+            direction: Vec3::X,
+        };
+        assert_eq!(tree.raycast(&ico, &away), None);
+        assert_eq!(tree.raycast(&ico, &beside), None);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for &sub in [0, 2, 4].iter() {
+            let ico = Ico::divs(sub);
+            let tree = FaceTree::build(&ico);
+            for ray in rays() {
+                let expected = ico
+                    .faces()
+                    .iter()
+                    .filter_map(|face| intersect(&ray, face))
+                    .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+                let hit = tree.raycast(&ico, &ray);
+                match (hit, expected) {
+                    (Some(hit), Some(expected)) => {
+                        assert!((hit.distance - expected.distance).abs() < 1e-5);
+                        // a ray through an edge may pick either face
+                        if hit.face != expected.face {
+                            assert!(hit.barycentric.min_element() < 1e-4);
+                        }
+                    }
+                    (hit, expected) => assert_eq!(hit, expected),
+                }
+            }
+        }
+    }
+}
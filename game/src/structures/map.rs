@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use engine::camera::transition::Bookmarks;
+use serde::{Deserialize, Serialize};
+
+pub const MAP_FILE: &str = "map.json";
+
+/// Editor settings saved alongside the map.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MapFile {
+    pub size: i32,
+    #[serde(default)]
+    pub bookmarks: Bookmarks,
+}
+
+impl Default for MapFile {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            bookmarks: Bookmarks::default(),
+        }
+    }
+}
+
+impl MapFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Falls back to the default map when `path` is missing or unreadable.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load(path).unwrap_or_else(|e| {
+            log::warn!("failed to load {:?}: {}", path, e);
+            Self::default()
+        })
+    }
+}
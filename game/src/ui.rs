@@ -1,6 +1,16 @@
 use std::{sync::Arc, time::Duration};
 
-use engine::{event::RunnerEvent, graphics::texture::Texture, parking_lot::Mutex, wgpu, winit};
+use engine::{
+    camera::transition::Bookmarks,
+    event::RunnerEvent,
+    graphics::{debug::ScreenMarker, texture::Texture},
+    parking_lot::Mutex,
+    queue,
+    timing::{self, Phase, TimingHistory},
+    wgpu, winit,
+};
+
+use crate::editor::Editor;
 
 pub struct UiValue<T>
 where
@@ -74,6 +84,15 @@ impl UiIo {
     }
 }
 
+/// Camera changes asked for by the UI, handled on the next update.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraRequest {
+    SaveBookmark(String),
+    GotoBookmark(String),
+    RemoveBookmark(String),
+    FrameSelected,
+}
+
 pub struct EditorState {
     pub size: UiValue<i32>,
     pub zoom: UiValue<f32>,
@@ -81,6 +100,12 @@ pub struct EditorState {
     pub light_mix: UiValue<f32>,
     pub samples: UiValue<i32>,
     pub samples_select: i32,
+    /// Pick faces by raycasting the ico instead of reading the id target.
+    pub cpu_picking: bool,
+    /// Draw face normals, tangents and sibling links.
+    pub debug_ico: UiValue<bool>,
+    /// Debug text markers on screen, drawn over everything.
+    pub markers: Vec<ScreenMarker>,
 
     pub frame_time: Duration,
     pub fps: f32,
@@ -92,6 +117,10 @@ pub struct EditorState {
 
     pub image_id: Option<imgui::TextureId>,
 
+    pub bookmarks: Bookmarks,
+    pub bookmark_name: imgui::ImString,
+    pub camera_request: Option<CameraRequest>,
+
     pub ui_io: Arc<Mutex<UiIo>>,
 }
 
@@ -104,6 +133,9 @@ impl Default for EditorState {
             light_mix: UiValue::new(0.5),
             samples: UiValue::new(1),
             samples_select: 0,
+            cpu_picking: false,
+            debug_ico: UiValue::new(false),
+            markers: Vec::new(),
             frame_time: Duration::from_secs_f32(1.0 / 60.0),
             fps: 60.0,
             target_fps: 60,
@@ -113,6 +145,11 @@ impl Default for EditorState {
             target_tick_rate: 100,
 
             image_id: None,
+
+            bookmarks: Bookmarks::default(),
+            bookmark_name: imgui::ImString::with_capacity(32),
+            camera_request: None,
+
             ui_io: Arc::new(Mutex::new(UiIo::new(false, false))),
         }
     }
@@ -125,6 +162,22 @@ pub struct EditorUi {
     pub ui_io: Arc<Mutex<UiIo>>,
 }
 
+/// A finished imgui frame, recorded by the ui pass of the editor graph.
+pub struct UiFrame<'a> {
+    renderer: &'a mut imgui_wgpu::Renderer,
+    draw_data: &'a imgui::DrawData,
+    queue: &'a wgpu::Queue,
+    device: &'a wgpu::Device,
+}
+
+impl UiFrame<'_> {
+    pub fn record<'p>(&'p mut self, pass: &mut wgpu::RenderPass<'p>) {
+        self.renderer
+            .render(self.draw_data, self.queue, self.device, pass)
+            .unwrap();
+    }
+}
+
 impl EditorUi {
     pub fn new(
         window: &winit::window::Window,
@@ -141,8 +194,32 @@ impl EditorUi {
         io.display_size = [sc_desc.width as f32, sc_desc.height as f32];
         io.font_global_scale = (1.0 / window.scale_factor()) as f32;
 
+        let mut platform = imgui_winit_support::WinitPlatform::init(&mut context);
+        platform.attach_window(
+            context.io_mut(),
+            &window,
+            imgui_winit_support::HiDpiMode::Default,
+        );
+
+        let renderer = Self::build_renderer(&mut context, device, queue, sc_desc, state);
+
+        EditorUi {
+            context,
+            renderer,
+            platform,
+            ui_io: Arc::clone(&state.ui_io),
+        }
+    }
+
+    fn build_renderer(
+        context: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        state: &mut EditorState,
+    ) -> imgui_wgpu::Renderer {
         let mut renderer = imgui_wgpu::Renderer::new(
-            &mut context,
+            context,
             device,
             queue,
             imgui_wgpu::RendererConfig {
@@ -153,13 +230,6 @@ impl EditorUi {
             },
         );
 
-        let mut platform = imgui_winit_support::WinitPlatform::init(&mut context);
-        platform.attach_window(
-            context.io_mut(),
-            &window,
-            imgui_winit_support::HiDpiMode::Default,
-        );
-
         let image = imgui_wgpu::Texture::new(
             device,
             &renderer,
@@ -181,13 +251,18 @@ impl EditorUi {
         );
 
         state.image_id = Some(renderer.textures.insert(image));
+        renderer
+    }
 
-        EditorUi {
-            context,
-            renderer,
-            platform,
-            ui_io: Arc::clone(&state.ui_io),
-        }
+    /// Only one imgui context may exist, so keep it and rebuild the renderer.
+    pub fn device_recreated(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        state: &mut EditorState,
+    ) {
+        self.renderer = Self::build_renderer(&mut self.context, device, queue, sc_desc, state);
     }
 
     pub fn handle_event(
@@ -267,6 +342,7 @@ impl EditorUi {
                     .range(1..=10000)
                     .flags(imgui::SliderFlags::ALWAYS_CLAMP)
                     .build(frame, &mut state.target_tick_rate);
+                Self::draw_timings(frame);
                 imgui::Slider::new(imgui::im_str!("Size"))
                     .range(0..=5)
                     .flags(imgui::SliderFlags::ALWAYS_CLAMP)
@@ -276,6 +352,9 @@ impl EditorUi {
                     .flags(imgui::SliderFlags::ALWAYS_CLAMP)
                     .build(frame, &mut state.zoom);
                 frame.checkbox(imgui::im_str!("Perspective"), &mut state.perspective);
+                frame.checkbox(imgui::im_str!("CPU picking"), &mut state.cpu_picking);
+                frame.checkbox(imgui::im_str!("Debug ico"), &mut state.debug_ico);
+                Self::draw_bookmarks(frame, state);
                 let values = vec![1, 2, 4, 8];
                 let items = values
                     .iter()
@@ -297,9 +376,91 @@ impl EditorUi {
             });
     }
 
+    fn draw_bookmarks(frame: &imgui::Ui, state: &mut EditorState) {
+        if !imgui::CollapsingHeader::new(imgui::im_str!("Bookmarks")).build(frame) {
+            return;
+        }
+        frame
+            .input_text(imgui::im_str!("Name"), &mut state.bookmark_name)
+            .build();
+        let name = state.bookmark_name.to_str().trim();
+        if frame.button(imgui::im_str!("Save view"), [0.0, 0.0]) && !name.is_empty() {
+            state.camera_request = Some(CameraRequest::SaveBookmark(name.to_owned()));
+        }
+        frame.same_line(0.0);
+        if frame.button(imgui::im_str!("Frame selected"), [0.0, 0.0]) {
+            state.camera_request = Some(CameraRequest::FrameSelected);
+        }
+        for (name, _) in state.bookmarks.iter() {
+            if frame.button(&imgui::ImString::new(name), [0.0, 0.0]) {
+                state.camera_request = Some(CameraRequest::GotoBookmark(name.to_owned()));
+            }
+            frame.same_line(0.0);
+            if frame.small_button(&imgui::ImString::new(format!("x##{}", name))) {
+                state.camera_request = Some(CameraRequest::RemoveBookmark(name.to_owned()));
+            }
+        }
+    }
+
+    fn draw_timings(frame: &imgui::Ui) {
+        let timings = timing::timings();
+        let timings = timings.lock();
+
+        let history = timings.frame().history_ms();
+        frame
+            .plot_lines(imgui::im_str!("frame ms"), &history)
+            .graph_size([0.0, 60.0])
+            .scale_min(0.0)
+            .build();
+        Self::draw_stats(frame, "frame", timings.frame());
+        for &phase in Phase::ALL.iter() {
+            Self::draw_stats(frame, phase.name(), timings.phase(phase));
+        }
+        for (label, history) in timings.gpu() {
+            Self::draw_stats(frame, &format!("gpu {}", label), history);
+        }
+
+        let queue = queue::queue_stats().report();
+        frame.label_text(
+            imgui::im_str!("input queue"),
+            &imgui::ImString::new(format!("{} (max {})", queue.depth, queue.max_depth)),
+        );
+        frame.label_text(
+            imgui::im_str!("dropped / coalesced"),
+            &imgui::ImString::new(format!("{} / {}", queue.dropped, queue.coalesced)),
+        );
+    }
+
+    fn draw_stats(frame: &imgui::Ui, label: &str, history: &TimingHistory) {
+        if let Some(stats) = history.stats() {
+            frame.label_text(
+                &imgui::ImString::new(label),
+                &imgui::ImString::new(format!(
+                    "{:.2} / {:.2} / {:.2} / {:.2}",
+                    stats.min.as_secs_f32() * 1000.0,
+                    stats.avg.as_secs_f32() * 1000.0,
+                    stats.p95.as_secs_f32() * 1000.0,
+                    stats.p99.as_secs_f32() * 1000.0,
+                )),
+            );
+        }
+    }
+
+    fn draw_markers(frame: &imgui::Ui, window: &winit::window::Window, state: &EditorState) {
+        // markers are in physical pixels, imgui works in logical ones
+        let scale = window.scale_factor() as f32;
+        let draw_list = frame.get_foreground_draw_list();
+        for marker in state.markers.iter() {
+            let color: [f32; 4] = marker.color.into();
+            let position = marker.position / scale;
+            draw_list.add_text(position.into(), color, &marker.text);
+        }
+    }
+
+    /// Builds the imgui frame and records it with the rest of the editor graph.
     pub fn render(
         &mut self,
-        state: &mut EditorState,
+        editor: &mut Editor,
         frame: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         queue: &wgpu::Queue,
@@ -328,26 +489,21 @@ impl EditorUi {
         //     }
         // }
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("block_pipeline_render_pass"),
-            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: &frame,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: None,
-        });
-
-        let frame = self.context.frame();
-        Self::draw(&frame, window, state);
-        self.platform.prepare_render(&frame, window);
-        let draw_data = frame.render();
-
-        self.renderer
-            .render(&draw_data, queue, device, &mut render_pass)
-            .unwrap();
+        let ui = self.context.frame();
+        Self::draw(&ui, window, &mut editor.state);
+        Self::draw_markers(&ui, window, &editor.state);
+        self.platform.prepare_render(&ui, window);
+        let draw_data = ui.render();
+
+        editor.render(
+            frame,
+            encoder,
+            UiFrame {
+                renderer: &mut self.renderer,
+                draw_data,
+                queue,
+                device,
+            },
+        );
     }
 }
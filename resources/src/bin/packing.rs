@@ -48,7 +48,7 @@ fn compile(
 
     for InputItem { label, input } in descriptions.inputs {
         match input {
-            Input::Image(ImageInput { paths, mipmaps, format }) => {
+            Input::Image(ImageInput { paths, mipmaps, format, sampler }) => {
                 let images = paths
                     .iter()
                     .map(|p| wd.join(Path::new("data")).join(p))
@@ -96,6 +96,7 @@ fn compile(
                         levels,
                         data: compressed,
                         format,
+                        sampler,
                     }),
                 });
             }
@@ -1,14 +1,75 @@
-use std::{io::prelude::*, num::NonZeroU32, path::PathBuf};
+use std::{
+    io::prelude::*,
+    num::{NonZeroU32, NonZeroU8},
+    path::PathBuf,
+};
 
 use flate2::read::ZlibDecoder;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ImageFormat {
     LinearRgb,
     Srgb,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressMode {
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+/// How an image is sampled, missing fields in the inputs take the default:
+/// clamped, trilinear and without LOD clamps.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerInfo {
+    /// u, v and w.
+    pub address_mode: [AddressMode; 3],
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    pub compare: Option<CompareFunction>,
+    /// 1, 2, 4, 8 or 16, anything else is rounded down when loaded.
+    pub anisotropy: Option<NonZeroU8>,
+}
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        Self {
+            address_mode: [AddressMode::ClampToEdge; 3],
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: f32::MAX,
+            compare: None,
+            anisotropy: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ImageRgba {
     pub size: (u32, u32),
@@ -16,6 +77,7 @@ pub struct ImageRgba {
     pub levels: u32,
     pub data: Vec<u8>,
     pub format: ImageFormat,
+    pub sampler: SamplerInfo,
 }
 
 impl ImageRgba {
@@ -54,6 +116,8 @@ pub struct ImageInput {
     pub paths: Vec<PathBuf>,
     pub mipmaps: Option<NonZeroU32>,
     pub format: ImageFormat,
+    #[serde(default)]
+    pub sampler: SamplerInfo,
 }
 
 #[derive(Debug, Serialize, Deserialize)]